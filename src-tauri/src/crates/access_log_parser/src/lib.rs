@@ -11,13 +11,21 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+mod owned;
 mod parsers;
+mod stream;
+
+pub use owned::*;
+pub use stream::*;
 
 use chrono::prelude::*;
-use nom::error::{self, VerboseError};
-use nom::Finish;
+use nom::error::{self, ErrorKind, VerboseError, VerboseErrorKind};
+use nom::{Finish, Offset};
 use parsers::compound;
+use serde::ser::{SerializeMap, Serializer};
 use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::net::IpAddr;
 use std::str::FromStr;
 use thiserror::Error;
@@ -29,7 +37,7 @@ pub struct CommonLogEntry<'a> {
     pub identd_user: Option<&'a str>,
     pub user: Option<&'a str>,
     pub timestamp: DateTime<FixedOffset>,
-    #[serde(skip)]
+    #[serde(flatten)]
     pub request: RequestResult<'a>,
     #[serde(with = "http_serde::status_code")]
     pub status_code: StatusCode,
@@ -42,7 +50,7 @@ pub struct CombinedLogEntry<'a> {
     pub identd_user: Option<&'a str>,
     pub user: Option<&'a str>,
     pub timestamp: DateTime<FixedOffset>,
-    #[serde(skip)]
+    #[serde(flatten)]
     pub request: RequestResult<'a>,
     #[serde(with = "http_serde::status_code")]
     pub status_code: http::StatusCode,
@@ -56,7 +64,7 @@ pub struct CombinedLogEntry<'a> {
 pub struct CloudControllerLogEntry<'a> {
     pub request_host: &'a str,
     pub timestamp: DateTime<FixedOffset>,
-    #[serde(skip)]
+    #[serde(flatten)]
     pub request: RequestResult<'a>,
     #[serde(with = "http_serde::status_code")]
     pub status_code: http::StatusCode,
@@ -73,7 +81,7 @@ pub struct CloudControllerLogEntry<'a> {
 pub struct GorouterLogEntry<'a> {
     pub request_host: &'a str,
     pub timestamp: DateTime<FixedOffset>,
-    #[serde(skip)]
+    #[serde(flatten)]
     pub request: RequestResult<'a>,
     #[serde(with = "http_serde::status_code")]
     pub status_code: http::StatusCode,
@@ -101,21 +109,165 @@ pub struct GorouterLogEntry<'a> {
 }
 
 #[derive(Debug, Serialize)]
+pub struct Rfc3164LogEntry<'a> {
+    pub priority: u8,
+    pub facility: SyslogFacility,
+    pub severity: SyslogSeverity,
+    /// BSD syslog timestamps carry neither a year nor a time zone, so the
+    /// year closest to now and UTC are assumed unless the sender used RFC 3339.
+    pub timestamp: DateTime<FixedOffset>,
+    pub hostname: Option<&'a str>,
+    pub app_name: Option<&'a str>,
+    pub procid: Option<&'a str>,
+    pub message: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Rfc5424LogEntry<'a> {
+    pub priority: u8,
+    pub facility: SyslogFacility,
+    pub severity: SyslogSeverity,
+    pub version: u8,
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub hostname: Option<&'a str>,
+    pub app_name: Option<&'a str>,
+    pub procid: Option<&'a str>,
+    pub msgid: Option<&'a str>,
+    pub structured_data: StructuredData<'a>,
+    pub message: Option<&'a str>,
+}
+
+/// Structured data elements of an RFC 5424 message, keyed by SD-ID and then by
+/// parameter name.
+pub type StructuredData<'a> = BTreeMap<&'a str, BTreeMap<&'a str, Cow<'a, str>>>;
+
+#[derive(Debug)]
 pub enum RequestResult<'a> {
-    #[serde(skip)]
     Valid(http::Request<()>),
-    
-    #[serde(skip)]
-    InvalidPath(&'a str, http::Error),
+    /// The path, the error, and the raw request line it came from.
+    InvalidPath(&'a str, http::Error, &'a str),
     InvalidRequest(&'a str),
 }
 
+/// Serializes the request line as flat `method`, `path`, `query` and `protocol`
+/// fields. Requests that could not be parsed keep their raw text in
+/// `raw_request`, plus the reason in `request_error` when it is known.
+impl Serialize for RequestResult<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        match self {
+            RequestResult::Valid(req) => RequestView::Valid(req),
+            RequestResult::InvalidPath(_, err, raw) => RequestView::InvalidPath(raw, err),
+            RequestResult::InvalidRequest(raw) => RequestView::InvalidRequest(raw),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Borrowed view shared by the serializers of `RequestResult` and
+/// `OwnedRequestResult`.
+enum RequestView<'r> {
+    Valid(&'r http::Request<()>),
+    InvalidPath(&'r str, &'r http::Error),
+    InvalidRequest(&'r str),
+}
+
+impl Serialize for RequestView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            RequestView::Valid(req) => {
+                map.serialize_entry("method", req.method().as_str())?;
+                map.serialize_entry("path", req.uri().path())?;
+                if let Some(query) = req.uri().query() {
+                    map.serialize_entry("query", &query_params(query))?;
+                }
+                map.serialize_entry("protocol", &format!("{:?}", req.version()))?;
+            }
+            RequestView::InvalidPath(raw, err) => {
+                map.serialize_entry("raw_request", raw)?;
+                map.serialize_entry("request_error", &err.to_string())?;
+            }
+            RequestView::InvalidRequest(raw) => {
+                map.serialize_entry("raw_request", raw)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// Decoded query parameters; a key repeated in the query keeps all its values
+/// in order.
+fn query_params(query: &str) -> BTreeMap<String, Vec<String>> {
+    let mut params: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        params
+            .entry(decode_query_component(key))
+            .or_default()
+            .push(decode_query_component(value));
+    }
+    params
+}
+
+/// Decodes `%XX` escapes and `+` as a space. Malformed escapes are kept as
+/// they are and invalid UTF-8 is replaced.
+fn decode_query_component(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if bytes.get(i + 1).is_some_and(u8::is_ascii_hexdigit)
+                && bytes.get(i + 2).is_some_and(u8::is_ascii_hexdigit) =>
+            {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
 #[derive(Debug, Serialize)]
 pub enum LogEntry<'a> {
     CommonLog(CommonLogEntry<'a>),
     CombinedLog(CombinedLogEntry<'a>),
     GorouterLog(GorouterLogEntry<'a>),
     CloudControllerLog(CloudControllerLogEntry<'a>),
+    Rfc3164Log(Rfc3164LogEntry<'a>),
+    Rfc5424Log(Rfc5424LogEntry<'a>),
+}
+
+impl LogEntry<'_> {
+    /// Converts the entry into an `OwnedLogEntry` that no longer borrows from
+    /// the parsed line.
+    pub fn into_owned(self) -> OwnedLogEntry {
+        match self {
+            LogEntry::CommonLog(e) => OwnedLogEntry::CommonLog(e.into()),
+            LogEntry::CombinedLog(e) => OwnedLogEntry::CombinedLog(e.into()),
+            LogEntry::GorouterLog(e) => OwnedLogEntry::GorouterLog(Box::new(e.into())),
+            LogEntry::CloudControllerLog(e) => OwnedLogEntry::CloudControllerLog(e.into()),
+            LogEntry::Rfc3164Log(e) => OwnedLogEntry::Rfc3164Log(e.into()),
+            LogEntry::Rfc5424Log(e) => OwnedLogEntry::Rfc5424Log(e.into()),
+        }
+    }
+
+    /// Serializes the fields of the entry, without the variant tag, straight
+    /// into a JSON value.
+    pub fn to_json_value(&self) -> serde_json::Result<serde_json::Value> {
+        match self {
+            LogEntry::CommonLog(e) => serde_json::to_value(e),
+            LogEntry::CombinedLog(e) => serde_json::to_value(e),
+            LogEntry::GorouterLog(e) => serde_json::to_value(e),
+            LogEntry::CloudControllerLog(e) => serde_json::to_value(e),
+            LogEntry::Rfc3164Log(e) => serde_json::to_value(e),
+            LogEntry::Rfc5424Log(e) => serde_json::to_value(e),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -124,6 +276,8 @@ pub enum LogType {
     CombinedLog,
     GorouterLog,
     CloudControllerLog,
+    Rfc3164Log,
+    Rfc5424Log,
 }
 
 impl FromStr for LogType {
@@ -135,6 +289,8 @@ impl FromStr for LogType {
             "combined" => Ok(LogType::CombinedLog),
             "gorouter" | "router" => Ok(LogType::GorouterLog),
             "cloud_controller" | "cc" | "capi" => Ok(LogType::CloudControllerLog),
+            "rfc3164" | "bsd_syslog" => Ok(LogType::Rfc3164Log),
+            "rfc5424" | "syslog" => Ok(LogType::Rfc5424Log),
             _ => Err("invalid log type"),
         }
     }
@@ -153,11 +309,193 @@ impl Default for XForwardedProto {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFacility {
+    Kern,
+    User,
+    Mail,
+    Daemon,
+    Auth,
+    Syslog,
+    Lpr,
+    News,
+    Uucp,
+    Cron,
+    AuthPriv,
+    Ftp,
+    Ntp,
+    Security,
+    Console,
+    SolarisCron,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl SyslogFacility {
+    /// Returns the facility encoded in a syslog priority value (`priority / 8`).
+    pub fn from_priority(priority: u8) -> Option<Self> {
+        use SyslogFacility::*;
+        Some(match priority >> 3 {
+            0 => Kern,
+            1 => User,
+            2 => Mail,
+            3 => Daemon,
+            4 => Auth,
+            5 => Syslog,
+            6 => Lpr,
+            7 => News,
+            8 => Uucp,
+            9 => Cron,
+            10 => AuthPriv,
+            11 => Ftp,
+            12 => Ntp,
+            13 => Security,
+            14 => Console,
+            15 => SolarisCron,
+            16 => Local0,
+            17 => Local1,
+            18 => Local2,
+            19 => Local3,
+            20 => Local4,
+            21 => Local5,
+            22 => Local6,
+            23 => Local7,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogSeverity {
+    Emergency,
+    Alert,
+    Critical,
+    Error,
+    Warning,
+    Notice,
+    Informational,
+    Debug,
+}
+
+impl SyslogSeverity {
+    /// Returns the severity encoded in a syslog priority value (`priority % 8`).
+    pub fn from_priority(priority: u8) -> Self {
+        use SyslogSeverity::*;
+        match priority & 0x7 {
+            0 => Emergency,
+            1 => Alert,
+            2 => Critical,
+            3 => Error,
+            4 => Warning,
+            5 => Notice,
+            6 => Informational,
+            _ => Debug,
+        }
+    }
+}
+
 /// AccessLogError enumerates all possible errors returned by this library
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind")]
 pub enum AccessLogError {
-    #[error("Parse error")]
-    ParseError { msg: String },
+    #[error(
+        "Parse error at column {} in {}: expected {}",
+        .detail.column,
+        .detail.field,
+        .detail.expected
+    )]
+    ParseError {
+        msg: String,
+        #[serde(flatten)]
+        detail: Box<ParseErrorDetail>,
+    },
+    #[error("Invalid log type: {log_type}")]
+    InvalidLogType { log_type: String },
+    #[error("No log types given")]
+    NoLogTypes,
+}
+
+/// Where and why a line stopped matching its format. `offset` is a byte offset
+/// and `column` a 1-based character column into the line, `field` is the
+/// innermost parser label at that point and `context` the full label stack
+/// from innermost to outermost.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ParseErrorDetail {
+    pub offset: usize,
+    pub column: usize,
+    pub field: &'static str,
+    pub context: Vec<&'static str>,
+    pub expected: String,
+    pub snippet: String,
+}
+
+const SNIPPET_LEN: usize = 24;
+
+fn parse_error(line: &str, e: VerboseError<&str>) -> AccessLogError {
+    let offset = e
+        .errors
+        .first()
+        .map(|(input, _)| line.offset(input))
+        .unwrap_or(0);
+    let context: Vec<&'static str> = e
+        .errors
+        .iter()
+        .filter_map(|(_, kind)| match kind {
+            VerboseErrorKind::Context(c) => Some(*c),
+            _ => None,
+        })
+        .collect();
+    let expected = match e.errors.first().map(|(_, kind)| kind) {
+        Some(VerboseErrorKind::Char(c)) => format!("'{}'", c),
+        Some(VerboseErrorKind::Nom(kind)) => expected_description(*kind),
+        Some(VerboseErrorKind::Context(c)) => c.to_string(),
+        None => "valid input".to_string(),
+    };
+
+    let column = line[..offset].chars().count() + 1;
+    // nom pads a caret out to the error column, and format widths past
+    // u16::MAX panic
+    let msg = if line.len() < u16::MAX as usize {
+        error::convert_error(line, e)
+    } else {
+        format!("expected {} at column {}", expected, column)
+    };
+
+    AccessLogError::ParseError {
+        detail: Box::new(ParseErrorDetail {
+            offset,
+            column,
+            field: context.first().copied().unwrap_or_default(),
+            context,
+            expected,
+            snippet: line[offset..].chars().take(SNIPPET_LEN).collect(),
+        }),
+        msg,
+    }
+}
+
+fn expected_description(kind: ErrorKind) -> String {
+    match kind {
+        ErrorKind::Tag => "literal text",
+        ErrorKind::Char => "character",
+        ErrorKind::Digit => "digits",
+        ErrorKind::Float => "number",
+        ErrorKind::MapRes => "valid value",
+        ErrorKind::Verify => "value in range",
+        ErrorKind::TakeUntil => "terminator",
+        ErrorKind::TakeWhile1 | ErrorKind::TakeTill1 | ErrorKind::IsNot => "non-empty value",
+        ErrorKind::Eof | ErrorKind::Complete => "more input",
+        kind => return kind.description().to_string(),
+    }
+    .to_string()
 }
 
 pub fn parse(log_type: LogType, line: &str) -> core::result::Result<LogEntry, AccessLogError> {
@@ -165,41 +503,64 @@ pub fn parse(log_type: LogType, line: &str) -> core::result::Result<LogEntry, Ac
         LogType::CommonLog => LogEntry::CommonLog(
             compound::common_log::<VerboseError<&str>>(line)
                 .finish()
-                .map_err(|e| AccessLogError::ParseError {
-                    msg: error::convert_error(line, e),
-                })?
+                .map_err(|e| parse_error(line, e))?
                 .1,
         ),
         LogType::CombinedLog => LogEntry::CombinedLog(
             compound::combined_log::<VerboseError<&str>>(line)
                 .finish()
-                .map_err(|e| AccessLogError::ParseError {
-                    msg: error::convert_error(line, e),
-                })?
+                .map_err(|e| parse_error(line, e))?
                 .1,
         ),
         LogType::CloudControllerLog => LogEntry::CloudControllerLog(
             compound::cloud_controller_log::<VerboseError<&str>>(line)
                 .finish()
-                .map_err(|e| AccessLogError::ParseError {
-                    msg: error::convert_error(line, e),
-                })?
+                .map_err(|e| parse_error(line, e))?
                 .1,
         ),
         LogType::GorouterLog => LogEntry::GorouterLog(
             compound::gorouter_log::<VerboseError<&str>>(line)
                 .finish()
-                .map_err(|e| AccessLogError::ParseError {
-                    msg: error::convert_error(line, e),
-                })?
+                .map_err(|e| parse_error(line, e))?
+                .1,
+        ),
+        LogType::Rfc3164Log => LogEntry::Rfc3164Log(
+            compound::rfc3164_log::<VerboseError<&str>>(line)
+                .finish()
+                .map_err(|e| parse_error(line, e))?
+                .1,
+        ),
+        LogType::Rfc5424Log => LogEntry::Rfc5424Log(
+            compound::rfc5424_log::<VerboseError<&str>>(line)
+                .finish()
+                .map_err(|e| parse_error(line, e))?
                 .1,
         ),
     })
 }
 
+/// Like `parse`, but without building an error report, which costs far more
+/// than the parse itself. For callers that only need to know whether a line
+/// is of `log_type`, e.g. when trying several formats.
+pub fn try_parse(log_type: LogType, line: &str) -> Option<LogEntry<'_>> {
+    Some(match log_type {
+        LogType::CommonLog => LogEntry::CommonLog(compound::common_log::<()>(line).ok()?.1),
+        LogType::CombinedLog => LogEntry::CombinedLog(compound::combined_log::<()>(line).ok()?.1),
+        LogType::CloudControllerLog => {
+            LogEntry::CloudControllerLog(compound::cloud_controller_log::<()>(line).ok()?.1)
+        }
+        LogType::GorouterLog => LogEntry::GorouterLog(compound::gorouter_log::<()>(line).ok()?.1),
+        LogType::Rfc3164Log => LogEntry::Rfc3164Log(compound::rfc3164_log::<()>(line).ok()?.1),
+        LogType::Rfc5424Log => LogEntry::Rfc5424Log(compound::rfc5424_log::<()>(line).ok()?.1),
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{parse, AccessLogError, LogType};
+    use crate::{
+        decode_query_component, parse, try_parse, AccessLogError, LogType, ParseErrorDetail,
+    };
+    use serde_json::json;
 
     #[test]
     fn parse_common_log() {
@@ -237,13 +598,200 @@ mod tests {
         assert!(entry.is_ok(), "{}", entry.err().unwrap());
     }
 
+    #[test]
+    fn serialize_request() {
+        let entry = parse(
+            LogType::CommonLog,
+            r#"127.0.0.1 - - [15/Mar/2019:03:17:05 +0000] "GET /search?q=rust&page=2 HTTP/1.1" 200 612"#,
+        )
+        .unwrap();
+        let value = serde_json::to_value(&entry).unwrap();
+        let entry = &value["CommonLog"];
+        assert_eq!(entry["method"], "GET");
+        assert_eq!(entry["path"], "/search");
+        assert_eq!(entry["query"], json!({ "q": ["rust"], "page": ["2"] }));
+        assert_eq!(entry["protocol"], "HTTP/1.1");
+        assert_eq!(entry["status_code"], 200);
+    }
+
+    #[test]
+    fn serialize_decoded_query() {
+        let entry = parse(
+            LogType::CommonLog,
+            r#"127.0.0.1 - - [15/Mar/2019:03:17:05 +0000] "GET /s?q=a%20b+c&tag=x&tag=y%2Cz&e=%zz&flag HTTP/1.1" 200 612"#,
+        )
+        .unwrap();
+        let value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(
+            value["CommonLog"]["query"],
+            json!({ "q": ["a b c"], "tag": ["x", "y,z"], "e": ["%zz"], "flag": [""] })
+        );
+        assert_eq!(decode_query_component("caf%C3%A9%"), "café%");
+    }
+
+    #[test]
+    fn serialize_invalid_request() {
+        let entry = parse(
+            LogType::CommonLog,
+            r#"127.0.0.1 - - [15/Mar/2019:03:17:05 +0000] "GET /?a=<php> HTTP/1.1" 400 0"#,
+        )
+        .unwrap();
+        let value = serde_json::to_value(&entry).unwrap();
+        let entry = &value["CommonLog"];
+        assert_eq!(entry["raw_request"], "GET /?a=<php> HTTP/1.1");
+        assert_eq!(entry["request_error"], "invalid uri character");
+        assert!(entry.get("method").is_none());
+
+        let entry = parse(
+            LogType::CommonLog,
+            r#"127.0.0.1 - - [15/Mar/2019:03:17:05 +0000] "\x16\x03\x01" 400 0"#,
+        )
+        .unwrap();
+        let value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(value["CommonLog"]["raw_request"], r#"\x16\x03\x01"#);
+    }
+
+    #[test]
+    fn to_json_value_omits_variant() {
+        let entry = parse(
+            LogType::CombinedLog,
+            r#"127.0.0.1 - - [15/Mar/2019:03:17:05 +0000] "GET / HTTP/1.1" 200 612 "http://www.example.com/foo" "foo user agent""#,
+        )
+        .unwrap();
+        let value = entry.to_json_value().unwrap();
+        assert_eq!(value["user_agent"], "foo user agent");
+        assert_eq!(value["method"], "GET");
+        assert_eq!(value, serde_json::to_value(&entry).unwrap()["CombinedLog"]);
+    }
+
+    #[test]
+    fn into_owned_serializes_like_borrowed() {
+        for (log_type, line) in [
+            (
+                LogType::GorouterLog,
+                r#"test.app_domain.example.com - [2019-01-28T22:15:08.622+0000] "PUT /eureka/apps/SERVICE-REGISTRY/service-registry:-1532850760?status=UP&lastDirtyTimestamp=1547950465746 HTTP/1.1" 404 0 116 "-" "Java-EurekaClient/v1.7.0" "10.224.20.205:23150" "-" x_forwarded_for:"10.179.113.63" x_forwarded_proto:"https" vcap_request_id:"762147e9-ecb8-41b2-4acd-2adc68122486" response_time:0.000119524 app_id:"-" app_index:"-" x_b3_traceid:"59ece3a70be6b6db" x_b3_spanid:"59ece3a70be6b6db" x_b3_parentspanid:"-""#,
+            ),
+            (
+                LogType::Rfc5424Log,
+                r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="Application" eventID="1011"] An application event log entry..."#,
+            ),
+        ] {
+            let entry = parse(log_type, line).unwrap();
+            let expected = serde_json::to_value(&entry).unwrap();
+            let owned = entry.into_owned();
+            assert_eq!(serde_json::to_value(&owned).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn owned_entry_outlives_line() {
+        let line = String::from(
+            r#"127.0.0.1 - frank [15/Mar/2019:03:17:05 +0000] "GET / HTTP/1.1" 200 612"#,
+        );
+        let owned = parse(LogType::CommonLog, &line).unwrap().into_owned();
+        drop(line);
+        let value = std::thread::spawn(move || owned.to_json_value().unwrap())
+            .join()
+            .unwrap();
+        assert_eq!(value["user"], "frank");
+    }
+
+    #[test]
+    fn parse_rfc3164() {
+        let entry = parse(
+            LogType::Rfc3164Log,
+            r#"<34>Oct 11 22:14:15 mymachine su: 'su root' failed for lonvick on /dev/pts/8"#,
+        );
+        assert!(entry.is_ok(), "{}", entry.err().unwrap());
+    }
+
+    #[test]
+    fn parse_rfc5424() {
+        let entry = parse(
+            LogType::Rfc5424Log,
+            r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="Application" eventID="1011"] An application event log entry..."#,
+        );
+        assert!(entry.is_ok(), "{}", entry.err().unwrap());
+    }
+
+    #[test]
+    fn try_parse_matches_parse() {
+        let line =
+            r#"<34>Oct 11 22:14:15 mymachine su: 'su root' failed for lonvick on /dev/pts/8"#;
+        for log_type in [LogType::CommonLog, LogType::Rfc5424Log, LogType::Rfc3164Log] {
+            assert_eq!(
+                try_parse(log_type, line).map(|e| e.to_json_value().unwrap()),
+                parse(log_type, line)
+                    .ok()
+                    .map(|e| e.to_json_value().unwrap()),
+            );
+        }
+        assert!(try_parse(LogType::Rfc3164Log, line).is_some());
+    }
+
     #[test]
     fn parse_error() {
         let entry = parse(LogType::CommonLog, "foo bar");
         assert!(entry.is_err());
         assert_eq!(
             entry.unwrap_err(),
-            AccessLogError::ParseError { msg: "0: at line 1, in MapRes:\nfoo bar\n^\n\n1: at line 1, in ip:\nfoo bar\n^\n\n2: at line 1, in common_log:\nfoo bar\n^\n\n".into() }
+            AccessLogError::ParseError {
+                msg: "0: at line 1, in MapRes:\nfoo bar\n^\n\n1: at line 1, in ip:\nfoo bar\n^\n\n2: at line 1, in common_log:\nfoo bar\n^\n\n".into(),
+                detail: Box::new(ParseErrorDetail {
+                    offset: 0,
+                    column: 1,
+                    field: "ip",
+                    context: vec!["ip", "common_log"],
+                    expected: "valid value".into(),
+                    snippet: "foo bar".into(),
+                }),
+            }
         );
     }
+
+    #[test]
+    fn parse_error_position() {
+        let line = r#"127.0.0.1 - - [15/Mar/2019:03:17:05 +0000] "GET / HTTP/1.1" abc 612"#;
+        match parse(LogType::CommonLog, line).unwrap_err() {
+            AccessLogError::ParseError { detail, .. } => {
+                assert_eq!(detail.offset, line.find("abc").unwrap());
+                assert_eq!(detail.column, detail.offset + 1);
+                assert_eq!(detail.field, "http_status");
+                assert_eq!(detail.context.last(), Some(&"common_log"));
+                assert_eq!(detail.snippet, "abc 612");
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn parse_error_long_line() {
+        let line = "x".repeat(70_000);
+        for log_type in [LogType::CloudControllerLog, LogType::GorouterLog].iter() {
+            match parse(*log_type, &line).unwrap_err() {
+                AccessLogError::ParseError { detail, msg } => {
+                    assert_eq!(detail.column, line.len() + 1);
+                    assert!(
+                        msg.contains(&format!("column {}", detail.column)),
+                        "{}",
+                        msg
+                    );
+                }
+                e => panic!("unexpected error: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_error_expected_char() {
+        let line = r#"<34>1 2003-10-11T22:14:15.003Z host app - - [id a=1]"#;
+        match parse(LogType::Rfc5424Log, line).unwrap_err() {
+            AccessLogError::ParseError { detail, .. } => {
+                assert_eq!(&line[detail.offset..], " a=1]");
+                assert_eq!(detail.field, "structured_data");
+                assert_eq!(detail.expected, "']'");
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
 }
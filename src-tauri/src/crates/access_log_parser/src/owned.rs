@@ -0,0 +1,315 @@
+// Copyright 2022 Daniel Mikusa
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+//     http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Owned counterparts of the borrowed log entry types. They serialize exactly
+//! like the types they are converted from, but can be stored or sent across
+//! threads independently of the parsed line.
+
+use chrono::{DateTime, FixedOffset};
+use serde::ser::Serializer;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use crate::{
+    CloudControllerLogEntry, CombinedLogEntry, CommonLogEntry, GorouterLogEntry, RequestResult,
+    RequestView, Rfc3164LogEntry, Rfc5424LogEntry, SyslogFacility, SyslogSeverity, XForwardedProto,
+};
+
+#[derive(Debug, Serialize)]
+pub struct OwnedCommonLogEntry {
+    pub ip: IpAddr,
+    pub identd_user: Option<String>,
+    pub user: Option<String>,
+    pub timestamp: DateTime<FixedOffset>,
+    #[serde(flatten)]
+    pub request: OwnedRequestResult,
+    #[serde(with = "http_serde::status_code")]
+    pub status_code: http::StatusCode,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnedCombinedLogEntry {
+    pub ip: IpAddr,
+    pub identd_user: Option<String>,
+    pub user: Option<String>,
+    pub timestamp: DateTime<FixedOffset>,
+    #[serde(flatten)]
+    pub request: OwnedRequestResult,
+    #[serde(with = "http_serde::status_code")]
+    pub status_code: http::StatusCode,
+    pub bytes: u64,
+    #[serde(with = "http_serde::option::uri")]
+    pub referrer: Option<http::Uri>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnedCloudControllerLogEntry {
+    pub request_host: String,
+    pub timestamp: DateTime<FixedOffset>,
+    #[serde(flatten)]
+    pub request: OwnedRequestResult,
+    #[serde(with = "http_serde::status_code")]
+    pub status_code: http::StatusCode,
+    pub bytes: u64,
+    #[serde(with = "http_serde::option::uri")]
+    pub referrer: Option<http::Uri>,
+    pub user_agent: Option<String>,
+    pub x_forwarded_for: Vec<IpAddr>,
+    pub vcap_request_id: Option<String>,
+    pub response_time: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnedGorouterLogEntry {
+    pub request_host: String,
+    pub timestamp: DateTime<FixedOffset>,
+    #[serde(flatten)]
+    pub request: OwnedRequestResult,
+    #[serde(with = "http_serde::status_code")]
+    pub status_code: http::StatusCode,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    #[serde(with = "http_serde::option::uri")]
+    pub referrer: Option<http::Uri>,
+    pub user_agent: Option<String>,
+    pub remote_addr: IpAddr,
+    pub remote_port: u16,
+    pub backend_addr: Option<IpAddr>,
+    pub backend_port: Option<u16>,
+    pub x_forwarded_for: Vec<IpAddr>,
+    pub x_forwarded_proto: XForwardedProto,
+    pub vcap_request_id: Option<String>,
+    pub response_time: Option<f64>,
+    pub gorouter_time: Option<f64>,
+    pub app_id: Option<String>,
+    pub app_index: Option<u16>,
+    pub instance_id: Option<String>,
+    pub x_cf_routererror: Option<String>,
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub parent_span_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnedRfc3164LogEntry {
+    pub priority: u8,
+    pub facility: SyslogFacility,
+    pub severity: SyslogSeverity,
+    pub timestamp: DateTime<FixedOffset>,
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    pub procid: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OwnedRfc5424LogEntry {
+    pub priority: u8,
+    pub facility: SyslogFacility,
+    pub severity: SyslogSeverity,
+    pub version: u8,
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    pub procid: Option<String>,
+    pub msgid: Option<String>,
+    pub structured_data: BTreeMap<String, BTreeMap<String, String>>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum OwnedRequestResult {
+    Valid(http::Request<()>),
+    /// The path, the error, and the raw request line it came from.
+    InvalidPath(String, http::Error, String),
+    InvalidRequest(String),
+}
+
+impl Serialize for OwnedRequestResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            OwnedRequestResult::Valid(req) => RequestView::Valid(req),
+            OwnedRequestResult::InvalidPath(_, err, raw) => RequestView::InvalidPath(raw, err),
+            OwnedRequestResult::InvalidRequest(raw) => RequestView::InvalidRequest(raw),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub enum OwnedLogEntry {
+    CommonLog(OwnedCommonLogEntry),
+    CombinedLog(OwnedCombinedLogEntry),
+    GorouterLog(Box<OwnedGorouterLogEntry>),
+    CloudControllerLog(OwnedCloudControllerLogEntry),
+    Rfc3164Log(OwnedRfc3164LogEntry),
+    Rfc5424Log(OwnedRfc5424LogEntry),
+}
+
+impl OwnedLogEntry {
+    /// Serializes the fields of the entry, without the variant tag, straight
+    /// into a JSON value.
+    pub fn to_json_value(&self) -> serde_json::Result<serde_json::Value> {
+        match self {
+            OwnedLogEntry::CommonLog(e) => serde_json::to_value(e),
+            OwnedLogEntry::CombinedLog(e) => serde_json::to_value(e),
+            OwnedLogEntry::GorouterLog(e) => serde_json::to_value(e),
+            OwnedLogEntry::CloudControllerLog(e) => serde_json::to_value(e),
+            OwnedLogEntry::Rfc3164Log(e) => serde_json::to_value(e),
+            OwnedLogEntry::Rfc5424Log(e) => serde_json::to_value(e),
+        }
+    }
+}
+
+fn owned(s: Option<&str>) -> Option<String> {
+    s.map(str::to_string)
+}
+
+impl From<RequestResult<'_>> for OwnedRequestResult {
+    fn from(request: RequestResult<'_>) -> Self {
+        match request {
+            RequestResult::Valid(req) => OwnedRequestResult::Valid(req),
+            RequestResult::InvalidPath(path, err, raw) => {
+                OwnedRequestResult::InvalidPath(path.to_string(), err, raw.to_string())
+            }
+            RequestResult::InvalidRequest(raw) => {
+                OwnedRequestResult::InvalidRequest(raw.to_string())
+            }
+        }
+    }
+}
+
+impl From<CommonLogEntry<'_>> for OwnedCommonLogEntry {
+    fn from(e: CommonLogEntry<'_>) -> Self {
+        OwnedCommonLogEntry {
+            ip: e.ip,
+            identd_user: owned(e.identd_user),
+            user: owned(e.user),
+            timestamp: e.timestamp,
+            request: e.request.into(),
+            status_code: e.status_code,
+            bytes: e.bytes,
+        }
+    }
+}
+
+impl From<CombinedLogEntry<'_>> for OwnedCombinedLogEntry {
+    fn from(e: CombinedLogEntry<'_>) -> Self {
+        OwnedCombinedLogEntry {
+            ip: e.ip,
+            identd_user: owned(e.identd_user),
+            user: owned(e.user),
+            timestamp: e.timestamp,
+            request: e.request.into(),
+            status_code: e.status_code,
+            bytes: e.bytes,
+            referrer: e.referrer,
+            user_agent: owned(e.user_agent),
+        }
+    }
+}
+
+impl From<CloudControllerLogEntry<'_>> for OwnedCloudControllerLogEntry {
+    fn from(e: CloudControllerLogEntry<'_>) -> Self {
+        OwnedCloudControllerLogEntry {
+            request_host: e.request_host.to_string(),
+            timestamp: e.timestamp,
+            request: e.request.into(),
+            status_code: e.status_code,
+            bytes: e.bytes,
+            referrer: e.referrer,
+            user_agent: owned(e.user_agent),
+            x_forwarded_for: e.x_forwarded_for,
+            vcap_request_id: owned(e.vcap_request_id),
+            response_time: e.response_time,
+        }
+    }
+}
+
+impl From<GorouterLogEntry<'_>> for OwnedGorouterLogEntry {
+    fn from(e: GorouterLogEntry<'_>) -> Self {
+        OwnedGorouterLogEntry {
+            request_host: e.request_host.to_string(),
+            timestamp: e.timestamp,
+            request: e.request.into(),
+            status_code: e.status_code,
+            bytes_received: e.bytes_received,
+            bytes_sent: e.bytes_sent,
+            referrer: e.referrer,
+            user_agent: owned(e.user_agent),
+            remote_addr: e.remote_addr,
+            remote_port: e.remote_port,
+            backend_addr: e.backend_addr,
+            backend_port: e.backend_port,
+            x_forwarded_for: e.x_forwarded_for,
+            x_forwarded_proto: e.x_forwarded_proto,
+            vcap_request_id: owned(e.vcap_request_id),
+            response_time: e.response_time,
+            gorouter_time: e.gorouter_time,
+            app_id: owned(e.app_id),
+            app_index: e.app_index,
+            instance_id: owned(e.instance_id),
+            x_cf_routererror: owned(e.x_cf_routererror),
+            trace_id: owned(e.trace_id),
+            span_id: owned(e.span_id),
+            parent_span_id: owned(e.parent_span_id),
+        }
+    }
+}
+
+impl From<Rfc3164LogEntry<'_>> for OwnedRfc3164LogEntry {
+    fn from(e: Rfc3164LogEntry<'_>) -> Self {
+        OwnedRfc3164LogEntry {
+            priority: e.priority,
+            facility: e.facility,
+            severity: e.severity,
+            timestamp: e.timestamp,
+            hostname: owned(e.hostname),
+            app_name: owned(e.app_name),
+            procid: owned(e.procid),
+            message: e.message.to_string(),
+        }
+    }
+}
+
+impl From<Rfc5424LogEntry<'_>> for OwnedRfc5424LogEntry {
+    fn from(e: Rfc5424LogEntry<'_>) -> Self {
+        OwnedRfc5424LogEntry {
+            priority: e.priority,
+            facility: e.facility,
+            severity: e.severity,
+            version: e.version,
+            timestamp: e.timestamp,
+            hostname: owned(e.hostname),
+            app_name: owned(e.app_name),
+            procid: owned(e.procid),
+            msgid: owned(e.msgid),
+            structured_data: e
+                .structured_data
+                .into_iter()
+                .map(|(id, params)| {
+                    let params = params
+                        .into_iter()
+                        .map(|(name, value)| (name.to_string(), value.into_owned()))
+                        .collect();
+                    (id.to_string(), params)
+                })
+                .collect(),
+            message: owned(e.message),
+        }
+    }
+}
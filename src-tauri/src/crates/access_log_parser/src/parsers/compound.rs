@@ -17,7 +17,7 @@ use std::net::AddrParseError;
 use nom::{
     bytes::complete::{take_while, take_while1},
     character::complete::char,
-    combinator::map,
+    combinator::{map, opt, rest},
     error::{context, ContextError, FromExternalError, ParseError},
     sequence::{terminated, tuple},
     IResult,
@@ -25,12 +25,14 @@ use nom::{
 
 use crate::{parsers::core::ip, CombinedLogEntry, GorouterLogEntry};
 use crate::{CloudControllerLogEntry, CommonLogEntry};
+use crate::{Rfc3164LogEntry, Rfc5424LogEntry, SyslogFacility, SyslogSeverity};
 
 use super::core::{
-    app_id, app_index, bytes, date, gorouter_time, http_status, idnetd_user, instance_id,
-    ip_and_port, ip_list, referrer, request, response_time, user, user_agent, vcap_request_id,
-    x_b3_parentspanid, x_b3_spanid, x_b3_traceid, x_cf_routererror, x_forwarded_for,
-    x_forwarded_proto,
+    app_id, app_index, app_name, bsd_timestamp, bytes, date, gorouter_time, hostname, http_status,
+    idnetd_user, instance_id, ip_and_port, ip_list, msgid, nil_or_timestamp, procid, referrer,
+    request, response_time, rfc3164_hostname, structured_data, syslog_message, syslog_priority,
+    syslog_tag, syslog_version, user, user_agent, vcap_request_id, x_b3_parentspanid, x_b3_spanid,
+    x_b3_traceid, x_cf_routererror, x_forwarded_for, x_forwarded_proto,
 };
 
 pub(crate) fn common_log<'a, E>(input: &'a str) -> IResult<&'a str, CommonLogEntry, E>
@@ -261,17 +263,98 @@ where
     )(input)
 }
 
+pub(crate) fn rfc3164_log<'a, E>(input: &'a str) -> IResult<&'a str, Rfc3164LogEntry<'a>, E>
+where
+    E: ParseError<&'a str>
+        + ContextError<&'a str>
+        + FromExternalError<&'a str, chrono::ParseError>
+        + FromExternalError<&'a str, std::num::ParseIntError>,
+{
+    context(
+        "rfc3164_log",
+        map(
+            tuple((
+                syslog_priority,
+                terminated(bsd_timestamp, char(' ')),
+                rfc3164_hostname,
+                opt(syslog_tag),
+                rest,
+            )),
+            |(priority, timestamp, hostname, tag, message)| Rfc3164LogEntry {
+                priority,
+                facility: SyslogFacility::from_priority(priority).unwrap(), // priority <= 191
+                severity: SyslogSeverity::from_priority(priority),
+                timestamp,
+                hostname,
+                app_name: tag.map(|(app_name, _)| app_name),
+                procid: tag.and_then(|(_, procid)| procid),
+                message,
+            },
+        ),
+    )(input)
+}
+
+pub(crate) fn rfc5424_log<'a, E>(input: &'a str) -> IResult<&'a str, Rfc5424LogEntry<'a>, E>
+where
+    E: ParseError<&'a str>
+        + ContextError<&'a str>
+        + FromExternalError<&'a str, chrono::ParseError>
+        + FromExternalError<&'a str, std::num::ParseIntError>,
+{
+    context(
+        "rfc5424_log",
+        map(
+            tuple((
+                syslog_priority,
+                terminated(syslog_version, char(' ')),
+                terminated(nil_or_timestamp, char(' ')),
+                terminated(hostname, char(' ')),
+                terminated(app_name, char(' ')),
+                terminated(procid, char(' ')),
+                terminated(msgid, char(' ')),
+                structured_data,
+                syslog_message,
+            )),
+            |(
+                priority,
+                version,
+                timestamp,
+                hostname,
+                app_name,
+                procid,
+                msgid,
+                structured_data,
+                message,
+            )| Rfc5424LogEntry {
+                priority,
+                facility: SyslogFacility::from_priority(priority).unwrap(), // priority <= 191
+                severity: SyslogSeverity::from_priority(priority),
+                version,
+                timestamp,
+                hostname,
+                app_name,
+                procid,
+                msgid,
+                structured_data,
+                message,
+            },
+        ),
+    )(input)
+}
+
 #[cfg(test)]
 mod tests {
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-    use chrono::{FixedOffset, NaiveDate, TimeZone};
+    use chrono::{Datelike, FixedOffset, NaiveDate, TimeZone};
     use http::Uri;
     use nom::error::VerboseError;
 
     use crate::{
-        parsers::compound::{cloud_controller_log, combined_log, gorouter_log},
-        RequestResult, XForwardedProto,
+        parsers::compound::{
+            cloud_controller_log, combined_log, gorouter_log, rfc3164_log, rfc5424_log,
+        },
+        RequestResult, SyslogFacility, SyslogSeverity, XForwardedProto,
     };
 
     use super::common_log;
@@ -935,4 +1018,132 @@ mod tests {
         assert_eq!(e.span_id, Some("03a4f586178193ab"));
         assert_eq!(e.parent_span_id, None);
     }
+
+    #[test]
+    fn parse_rfc3164_log_entry() {
+        let data =
+            r#"<34>Oct 11 22:14:15 mymachine su: 'su root' failed for lonvick on /dev/pts/8"#;
+        let res = rfc3164_log::<VerboseError<&str>>(data);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let e = res.unwrap().1;
+        assert_eq!(e.priority, 34);
+        assert_eq!(e.facility, SyslogFacility::Auth);
+        assert_eq!(e.severity, SyslogSeverity::Critical);
+        assert_eq!((e.timestamp.month(), e.timestamp.day()), (10, 11));
+        assert_eq!(e.hostname, Some("mymachine"));
+        assert_eq!(e.app_name, Some("su"));
+        assert_eq!(e.procid, None);
+        assert_eq!(e.message, "'su root' failed for lonvick on /dev/pts/8");
+    }
+
+    #[test]
+    fn parse_rfc3164_log_entry_with_pid() {
+        let data =
+            r#"<86>2019-01-28T22:15:08.622+00:00 node-1 sshd[4321]: Accepted publickey for core"#;
+        let res = rfc3164_log::<VerboseError<&str>>(data);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let e = res.unwrap().1;
+        assert_eq!(e.facility, SyslogFacility::AuthPriv);
+        assert_eq!(e.severity, SyslogSeverity::Informational);
+        assert_eq!(
+            e.timestamp,
+            FixedOffset::west_opt(0)
+                .unwrap()
+                .from_local_datetime(
+                    &NaiveDate::from_ymd_opt(2019, 1, 28)
+                        .unwrap()
+                        .and_hms_milli_opt(22, 15, 8, 622)
+                        .unwrap()
+                )
+                .unwrap()
+        );
+        assert_eq!(e.hostname, Some("node-1"));
+        assert_eq!(e.app_name, Some("sshd"));
+        assert_eq!(e.procid, Some("4321"));
+        assert_eq!(e.message, "Accepted publickey for core");
+    }
+
+    #[test]
+    fn parse_rfc3164_log_entry_without_tag() {
+        let data = r#"<13>Feb  5 17:32:18 10.0.0.99 Use the BFG!"#;
+        let res = rfc3164_log::<VerboseError<&str>>(data);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let e = res.unwrap().1;
+        assert_eq!(e.facility, SyslogFacility::User);
+        assert_eq!(e.severity, SyslogSeverity::Notice);
+        assert_eq!(e.hostname, Some("10.0.0.99"));
+        assert_eq!(e.app_name, None);
+        assert_eq!(e.message, "Use the BFG!");
+    }
+
+    #[test]
+    fn parse_rfc3164_log_entry_without_hostname() {
+        let data = r#"<34>Oct 11 22:14:15 su: 'su root' failed for lonvick on /dev/pts/8"#;
+        let res = rfc3164_log::<VerboseError<&str>>(data);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let e = res.unwrap().1;
+        assert_eq!(e.hostname, None);
+        assert_eq!(e.app_name, Some("su"));
+        assert_eq!(e.message, "'su root' failed for lonvick on /dev/pts/8");
+
+        let data = r#"<86>Oct 11 22:14:15 sshd[4321]: Accepted publickey for core"#;
+        let e = rfc3164_log::<VerboseError<&str>>(data).unwrap().1;
+        assert_eq!(e.hostname, None);
+        assert_eq!((e.app_name, e.procid), (Some("sshd"), Some("4321")));
+
+        // A colon inside the hostname doesn't make it a tag
+        let data = r#"<34>Oct 11 22:14:15 fe80::1 su: failed"#;
+        let e = rfc3164_log::<VerboseError<&str>>(data).unwrap().1;
+        assert_eq!(e.hostname, Some("fe80::1"));
+        assert_eq!(e.app_name, Some("su"));
+    }
+
+    #[test]
+    fn parse_rfc5424_log_entry() {
+        let data = r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="Application" eventID="1011"] An application event log entry..."#;
+        let res = rfc5424_log::<VerboseError<&str>>(data);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let e = res.unwrap().1;
+        assert_eq!(e.priority, 165);
+        assert_eq!(e.facility, SyslogFacility::Local4);
+        assert_eq!(e.severity, SyslogSeverity::Notice);
+        assert_eq!(e.version, 1);
+        assert_eq!(
+            e.timestamp,
+            Some(
+                FixedOffset::west_opt(0)
+                    .unwrap()
+                    .from_local_datetime(
+                        &NaiveDate::from_ymd_opt(2003, 10, 11)
+                            .unwrap()
+                            .and_hms_milli_opt(22, 14, 15, 3)
+                            .unwrap()
+                    )
+                    .unwrap()
+            )
+        );
+        assert_eq!(e.hostname, Some("mymachine.example.com"));
+        assert_eq!(e.app_name, Some("evntslog"));
+        assert_eq!(e.procid, None);
+        assert_eq!(e.msgid, Some("ID47"));
+        assert_eq!(e.structured_data["exampleSDID@32473"]["eventID"], "1011");
+        assert_eq!(e.message, Some("An application event log entry..."));
+    }
+
+    #[test]
+    fn parse_rfc5424_log_entry_with_nil_values() {
+        let data = r#"<34>1 - - - - - -"#;
+        let res = rfc5424_log::<VerboseError<&str>>(data);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let e = res.unwrap().1;
+        assert_eq!(e.facility, SyslogFacility::Auth);
+        assert_eq!(e.severity, SyslogSeverity::Critical);
+        assert_eq!(e.timestamp, None);
+        assert_eq!(e.hostname, None);
+        assert_eq!(e.app_name, None);
+        assert_eq!(e.procid, None);
+        assert_eq!(e.msgid, None);
+        assert!(e.structured_data.is_empty());
+        assert_eq!(e.message, None);
+    }
 }
@@ -12,25 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDateTime, TimeZone, Utc};
 use http::uri::InvalidUri;
 use nom::{
     branch::alt,
-    bytes::complete::{is_a, tag, take_until, take_until1, take_while},
-    character::complete::char,
-    combinator::{map, map_res, opt, success},
+    bytes::complete::{
+        escaped, is_a, is_not, tag, take, take_till1, take_until, take_until1, take_while,
+        take_while1,
+    },
+    character::complete::{anychar, char, digit1},
+    combinator::{consumed, eof, map, map_res, opt, peek, rest, success, verify},
     error::{context, ContextError, FromExternalError, ParseError},
-    multi::separated_list0,
+    multi::{many0, many1, separated_list0},
     number::complete::double,
-    sequence::{delimited, preceded, separated_pair, terminated, tuple},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     AsChar, IResult,
 };
 use std::{
+    borrow::Cow,
+    collections::BTreeMap,
     net::{AddrParseError, IpAddr},
     num::ParseIntError,
 };
 
-use crate::{RequestResult, XForwardedProto};
+use crate::{RequestResult, StructuredData, XForwardedProto};
 
 pub(super) fn dash_or_str<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
@@ -155,7 +160,7 @@ pub(super) fn protocol_version<'a, E: ParseError<&'a str> + ContextError<&'a str
         alt((
             map(tag("HTTP/1.0"), |_| http::Version::HTTP_10),
             map(tag("HTTP/1.1"), |_| http::Version::HTTP_11),
-            map(tag("HTTP/2.0"), |_| http::Version::HTTP_11),
+            map(tag("HTTP/2.0"), |_| http::Version::HTTP_2),
         )),
     )(input)
 }
@@ -170,12 +175,12 @@ where
             delimited(
                 char('"'),
                 map(
-                    tuple((
+                    consumed(tuple((
                         terminated(method, char(' ')),
                         terminated(path, char(' ')),
                         protocol_version,
-                    )),
-                    |(m, p, v)| {
+                    ))),
+                    |(raw, (m, p, v))| {
                         let req = http::Request::builder()
                             .method(m)
                             .uri(p)
@@ -183,7 +188,7 @@ where
                             .body(());
                         match req {
                             Ok(r) => RequestResult::Valid(r),
-                            Err(err) => RequestResult::InvalidPath(p, err),
+                            Err(err) => RequestResult::InvalidPath(p, err, raw),
                         }
                     },
                 ),
@@ -478,6 +483,224 @@ where
     )(input)
 }
 
+pub(super) fn syslog_priority<'a, E>(input: &'a str) -> IResult<&'a str, u8, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str> + FromExternalError<&'a str, ParseIntError>,
+{
+    context(
+        "syslog_priority",
+        verify(
+            delimited(
+                char('<'),
+                map_res(digit1, |p: &str| p.parse::<u8>()),
+                char('>'),
+            ),
+            |p: &u8| *p <= 191,
+        ),
+    )(input)
+}
+
+pub(super) fn syslog_version<'a, E>(input: &'a str) -> IResult<&'a str, u8, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str> + FromExternalError<&'a str, ParseIntError>,
+{
+    context("syslog_version", map_res(digit1, |v: &str| v.parse()))(input)
+}
+
+pub(super) fn rfc3339_timestamp<'a, E>(input: &'a str) -> IResult<&'a str, DateTime<FixedOffset>, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str> + FromExternalError<&'a str, chrono::ParseError>,
+{
+    context(
+        "rfc3339_timestamp",
+        map_res(take_till1(|c: char| c == ' '), DateTime::parse_from_rfc3339),
+    )(input)
+}
+
+pub(super) fn bsd_timestamp<'a, E>(input: &'a str) -> IResult<&'a str, DateTime<FixedOffset>, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str> + FromExternalError<&'a str, chrono::ParseError>,
+{
+    context(
+        "bsd_timestamp",
+        alt((
+            rfc3339_timestamp,
+            map_res(take(15usize), |d: &str| bsd_datetime(d, Utc::now())),
+        )),
+    )(input)
+}
+
+/// Resolves a year-less BSD timestamp to the year that puts it closest to
+/// `now`, so a December entry read in January lands in the previous year.
+fn bsd_datetime(d: &str, now: DateTime<Utc>) -> Result<DateTime<FixedOffset>, chrono::ParseError> {
+    let parse =
+        |year: i32| NaiveDateTime::parse_from_str(&format!("{} {}", year, d), "%Y %b %e %H:%M:%S");
+    let closest = [now.year() - 1, now.year(), now.year() + 1]
+        .iter()
+        .filter_map(|year| parse(*year).ok())
+        .min_by_key(|t| (t.and_utc() - now).num_seconds().abs());
+    let t = match closest {
+        Some(t) => t,
+        None => parse(now.year())?,
+    };
+    Ok(FixedOffset::east_opt(0).unwrap().from_utc_datetime(&t))
+}
+
+pub(super) fn nil_or_timestamp<'a, E>(
+    input: &'a str,
+) -> IResult<&'a str, Option<DateTime<FixedOffset>>, E>
+where
+    E: ParseError<&'a str> + ContextError<&'a str> + FromExternalError<&'a str, chrono::ParseError>,
+{
+    context(
+        "timestamp",
+        alt((map(tag("-"), |_| None), map(rfc3339_timestamp, Some))),
+    )(input)
+}
+
+pub(super) fn hostname<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Option<&'a str>, E> {
+    context("hostname", dash_or_str)(input)
+}
+
+pub(super) fn app_name<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Option<&'a str>, E> {
+    context("app_name", dash_or_str)(input)
+}
+
+pub(super) fn procid<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Option<&'a str>, E> {
+    context("procid", dash_or_str)(input)
+}
+
+pub(super) fn msgid<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Option<&'a str>, E> {
+    context("msgid", dash_or_str)(input)
+}
+
+fn syslog_tag_name<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, (&'a str, Option<&'a str>), E> {
+    pair(
+        take_while1(|c: char| c.is_alphanumeric() || "-_./".contains(c)),
+        opt(delimited(char('['), take_until("]"), char(']'))),
+    )(input)
+}
+
+pub(super) fn syslog_tag<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, (&'a str, Option<&'a str>), E> {
+    context(
+        "syslog_tag",
+        terminated(syslog_tag_name, pair(char(':'), opt(char(' ')))),
+    )(input)
+}
+
+/// The hostname of an RFC 3164 header and the space after it. Many BSD
+/// senders leave it out, so there is none when a `tag:` or `tag[pid]:`
+/// directly follows the timestamp.
+pub(super) fn rfc3164_hostname<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Option<&'a str>, E> {
+    alt((
+        map(
+            peek(pair(
+                syslog_tag_name,
+                alt((tag(": "), terminated(tag(":"), eof))),
+            )),
+            |_| None,
+        ),
+        terminated(hostname, char(' ')),
+    ))(input)
+}
+
+fn sd_name<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, &'a str, E> {
+    context(
+        "sd_name",
+        take_while1(|c: char| !c.is_whitespace() && !"=]\"".contains(c)),
+    )(input)
+}
+
+fn sd_param_value<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Cow<'a, str>, E> {
+    context(
+        "sd_param_value",
+        delimited(
+            char('"'),
+            map(
+                alt((escaped(is_not("\\\""), '\\', anychar), success(""))),
+                |v: &'a str| {
+                    if !v.contains('\\') {
+                        return Cow::Borrowed(v);
+                    }
+                    let mut unescaped = String::with_capacity(v.len());
+                    let mut chars = v.chars().peekable();
+                    while let Some(c) = chars.next() {
+                        match (c, chars.peek()) {
+                            ('\\', Some('"' | '\\' | ']')) => unescaped.push(chars.next().unwrap()),
+                            _ => unescaped.push(c),
+                        }
+                    }
+                    Cow::Owned(unescaped)
+                },
+            ),
+            char('"'),
+        ),
+    )(input)
+}
+
+pub(super) fn structured_data<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, StructuredData<'a>, E> {
+    context(
+        "structured_data",
+        alt((
+            map(tag("-"), |_| BTreeMap::new()),
+            map(
+                many1(delimited(
+                    char('['),
+                    pair(
+                        sd_name,
+                        many0(preceded(
+                            char(' '),
+                            separated_pair(sd_name, char('='), sd_param_value),
+                        )),
+                    ),
+                    char(']'),
+                )),
+                |elements| {
+                    elements
+                        .into_iter()
+                        .map(|(id, params)| (id, params.into_iter().collect()))
+                        .collect()
+                },
+            ),
+        )),
+    )(input)
+}
+
+pub(super) fn syslog_message<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Option<&'a str>, E> {
+    context(
+        "syslog_message",
+        map(
+            opt(preceded(
+                char(' '),
+                map(rest, |m: &str| m.strip_prefix('\u{feff}').unwrap_or(m)),
+            )),
+            |m| m.filter(|m| !m.is_empty()),
+        ),
+    )(input)
+}
+
 #[cfg(test)]
 mod core_tests {
     use super::*;
@@ -610,6 +833,22 @@ mod core_tests {
         }
     }
 
+    #[test]
+    fn parse_protocol_version() {
+        for (raw, version) in [
+            ("HTTP/1.0", http::Version::HTTP_10),
+            ("HTTP/1.1", http::Version::HTTP_11),
+            ("HTTP/2.0", http::Version::HTTP_2),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                protocol_version::<VerboseError<&str>>(raw),
+                Ok(("", *version))
+            );
+        }
+    }
+
     #[test]
     fn parse_request_empty() {
         let internal = "\"";
@@ -652,7 +891,10 @@ mod core_tests {
         let res = request::<VerboseError<&str>>(&data);
         assert!(res.is_ok());
         let (m, merr) = match res.unwrap().1 {
-            RequestResult::InvalidPath(p, err) => (p, err),
+            RequestResult::InvalidPath(p, err, raw) => {
+                assert_eq!(raw, inner);
+                (p, err)
+            }
             _ => panic!("should not happen"),
         };
         assert_eq!("/?a=fetch&content=<php>die(@md5(HelloThinkCMF))</php>", m);
@@ -1027,4 +1269,121 @@ mod core_tests {
         assert!(res.is_ok());
         assert_eq!(res.unwrap().1, None);
     }
+
+    #[test]
+    fn parse_syslog_priority() {
+        assert_eq!(
+            syslog_priority::<VerboseError<&str>>("<34>Oct"),
+            Ok(("Oct", 34))
+        );
+        assert_eq!(syslog_priority::<VerboseError<&str>>("<0>1"), Ok(("1", 0)));
+        assert!(syslog_priority::<VerboseError<&str>>("<192>1").is_err());
+        assert!(syslog_priority::<VerboseError<&str>>("34>1").is_err());
+    }
+
+    #[test]
+    fn parse_bsd_timestamp() {
+        let res = bsd_timestamp::<VerboseError<&str>>("Oct  1 22:14:15 host");
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let (rem, ts) = res.unwrap();
+        assert_eq!(rem, " host");
+        assert_eq!((ts.month(), ts.day()), (10, 1));
+        assert_eq!(
+            ts.time(),
+            chrono::NaiveTime::from_hms_opt(22, 14, 15).unwrap()
+        );
+
+        let expected = FixedOffset::west_opt(7 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2000, 7, 25, 13, 55, 36)
+            .unwrap();
+        assert_eq!(
+            bsd_timestamp::<VerboseError<&str>>("2000-07-25T13:55:36-07:00 host"),
+            Ok((" host", expected))
+        );
+    }
+
+    #[test]
+    fn bsd_timestamp_year_boundary() {
+        let at = |y, m, d| Utc.with_ymd_and_hms(y, m, d, 12, 0, 0).unwrap();
+
+        let ts = bsd_datetime("Dec 31 23:59:59", at(2024, 1, 2)).unwrap();
+        assert_eq!((ts.year(), ts.month(), ts.day()), (2023, 12, 31));
+
+        let ts = bsd_datetime("Jan  1 00:00:01", at(2023, 12, 31)).unwrap();
+        assert_eq!((ts.year(), ts.month(), ts.day()), (2024, 1, 1));
+
+        let ts = bsd_datetime("Jun 15 08:00:00", at(2024, 6, 14)).unwrap();
+        assert_eq!(ts.year(), 2024);
+
+        // Only leap years have a Feb 29
+        let ts = bsd_datetime("Feb 29 10:00:00", at(2025, 3, 1)).unwrap();
+        assert_eq!(ts.year(), 2024);
+        assert!(bsd_datetime("Feb 30 10:00:00", at(2025, 3, 1)).is_err());
+    }
+
+    #[test]
+    fn parse_nil_or_timestamp() {
+        assert_eq!(
+            nil_or_timestamp::<VerboseError<&str>>("- "),
+            Ok((" ", None))
+        );
+        assert_eq!(
+            nil_or_timestamp::<VerboseError<&str>>("2003-10-11T22:14:15.003Z "),
+            Ok((
+                " ",
+                Some(DateTime::parse_from_rfc3339("2003-10-11T22:14:15.003Z").unwrap())
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_syslog_tag() {
+        assert_eq!(
+            syslog_tag::<VerboseError<&str>>("sshd[1234]: Accepted"),
+            Ok(("Accepted", ("sshd", Some("1234"))))
+        );
+        assert_eq!(
+            syslog_tag::<VerboseError<&str>>("su: failed"),
+            Ok(("failed", ("su", None)))
+        );
+        assert!(syslog_tag::<VerboseError<&str>>("no tag here").is_err());
+    }
+
+    #[test]
+    fn parse_structured_data() {
+        let res = structured_data::<VerboseError<&str>>(
+            r#"[exampleSDID@32473 iut="3" eventSource="Application"][examplePriority@32473 class="high"] msg"#,
+        );
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let (rem, sd) = res.unwrap();
+        assert_eq!(rem, " msg");
+        assert_eq!(sd.len(), 2);
+        assert_eq!(sd["exampleSDID@32473"]["iut"], "3");
+        assert_eq!(sd["exampleSDID@32473"]["eventSource"], "Application");
+        assert_eq!(sd["examplePriority@32473"]["class"], "high");
+
+        assert_eq!(
+            structured_data::<VerboseError<&str>>("- msg"),
+            Ok((" msg", BTreeMap::new()))
+        );
+    }
+
+    #[test]
+    fn parse_structured_data_with_escapes() {
+        let res = structured_data::<VerboseError<&str>>(r#"[id a="x\"y\]z" b=""]"#);
+        assert!(res.is_ok(), "{}", res.unwrap_err());
+        let sd = res.unwrap().1;
+        assert_eq!(sd["id"]["a"], r#"x"y]z"#);
+        assert_eq!(sd["id"]["b"], "");
+    }
+
+    #[test]
+    fn parse_syslog_message() {
+        assert_eq!(
+            syslog_message::<VerboseError<&str>>(" \u{feff}hello"),
+            Ok(("", Some("hello")))
+        );
+        assert_eq!(syslog_message::<VerboseError<&str>>(""), Ok(("", None)));
+    }
 }
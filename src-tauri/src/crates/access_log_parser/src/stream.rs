@@ -0,0 +1,242 @@
+// Copyright 2022 Daniel Mikusa
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+
+//     http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parsing of whole log files or streams, one entry per line. Lines that fail
+//! to parse are reported with their line number instead of aborting the stream.
+
+use std::io::{self, BufRead};
+use std::thread;
+use thiserror::Error;
+
+use crate::{parse, try_parse, AccessLogError, LogEntry, LogType, OwnedLogEntry};
+
+/// A successfully parsed line, together with its 1-based line number and the
+/// format that matched it.
+#[derive(Debug)]
+pub struct ParsedLine<T> {
+    pub line_number: usize,
+    pub log_type: LogType,
+    pub entry: T,
+}
+
+#[derive(Error, Debug)]
+pub enum LineError {
+    #[error("line {line_number}: {source}")]
+    Io {
+        line_number: usize,
+        source: io::Error,
+    },
+    #[error("line {line_number}: {error}")]
+    Parse {
+        line_number: usize,
+        line: String,
+        error: AccessLogError,
+    },
+}
+
+/// Tries each of `log_types` in order and returns the first entry that parses.
+/// If none match, the error for the first (preferred) format is returned.
+pub fn parse_with_fallback<'a>(
+    log_types: &[LogType],
+    line: &'a str,
+) -> Result<(LogType, LogEntry<'a>), AccessLogError> {
+    for log_type in log_types {
+        if let Some(entry) = try_parse(*log_type, line) {
+            return Ok((*log_type, entry));
+        }
+    }
+    // Only the reported error is worth the cost of a full report
+    match log_types.first() {
+        Some(log_type) => parse(*log_type, line).map(|entry| (*log_type, entry)),
+        None => Err(AccessLogError::NoLogTypes),
+    }
+}
+
+/// Iterator over the entries of a `BufRead`, yielding one result per non-blank
+/// line.
+pub struct LogReader<R> {
+    lines: io::Lines<R>,
+    log_types: Vec<LogType>,
+    line_number: usize,
+}
+
+impl<R: BufRead> LogReader<R> {
+    pub fn new(reader: R, log_type: LogType) -> Self {
+        Self::with_fallback(reader, vec![log_type])
+    }
+
+    /// Creates a reader that tries `log_types` in order for every line, so
+    /// files that mix several formats can be read in one pass.
+    pub fn with_fallback(reader: R, log_types: Vec<LogType>) -> Self {
+        LogReader {
+            lines: reader.lines(),
+            log_types,
+            line_number: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for LogReader<R> {
+    type Item = Result<ParsedLine<OwnedLogEntry>, LineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.lines.next()?;
+            self.line_number += 1;
+            let line_number = self.line_number;
+
+            let line = match line {
+                Ok(line) => line,
+                Err(source) => {
+                    return Some(Err(LineError::Io {
+                        line_number,
+                        source,
+                    }))
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            return Some(
+                parse_line(&self.log_types, line_number, &line).map(|parsed| ParsedLine {
+                    line_number,
+                    log_type: parsed.log_type,
+                    entry: parsed.entry.into_owned(),
+                }),
+            );
+        }
+    }
+}
+
+fn parse_line<'a>(
+    log_types: &[LogType],
+    line_number: usize,
+    line: &'a str,
+) -> Result<ParsedLine<LogEntry<'a>>, LineError> {
+    parse_with_fallback(log_types, line)
+        .map(|(log_type, entry)| ParsedLine {
+            line_number,
+            log_type,
+            entry,
+        })
+        .map_err(|error| LineError::Parse {
+            line_number,
+            line: line.to_string(),
+            error,
+        })
+}
+
+/// Parses every non-blank line of `input`, splitting the work into contiguous
+/// chunks across `threads` threads. Results are returned in line order.
+pub fn parse_parallel<'a>(
+    log_types: &[LogType],
+    input: &'a str,
+    threads: usize,
+) -> Vec<Result<ParsedLine<LogEntry<'a>>, LineError>> {
+    let lines: Vec<(usize, &str)> = input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| (i + 1, line))
+        .collect();
+    if lines.is_empty() {
+        return Vec::new();
+    }
+    let chunk_size = lines.len().div_ceil(threads.max(1));
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = lines
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(line_number, line)| parse_line(log_types, *line_number, line))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const INPUT: &str = r#"127.0.0.1 - - [15/Mar/2019:03:17:05 +0000] "GET / HTTP/1.1" 200 612
+not a log line
+
+<34>1 2003-10-11T22:14:15.003Z mymachine su - ID47 - 'su root' failed
+127.0.0.1 - frank [15/Mar/2019:03:17:06 +0000] "POST /login HTTP/1.1" 302 0
+"#;
+
+    #[test]
+    fn read_lines_with_errors() {
+        let results: Vec<_> = LogReader::new(Cursor::new(INPUT), LogType::CommonLog).collect();
+        assert_eq!(results.len(), 4);
+        assert!(matches!(&results[0], Ok(p) if p.line_number == 1));
+        assert!(
+            matches!(&results[1], Err(LineError::Parse { line_number: 2, line, .. }) if line == "not a log line")
+        );
+        assert!(matches!(
+            &results[2],
+            Err(LineError::Parse { line_number: 4, .. })
+        ));
+        assert!(matches!(&results[3], Ok(p) if p.line_number == 5));
+    }
+
+    #[test]
+    fn read_mixed_formats() {
+        let results: Vec<_> = LogReader::with_fallback(
+            Cursor::new(INPUT),
+            vec![LogType::CommonLog, LogType::Rfc5424Log],
+        )
+        .collect();
+        assert_eq!(results.len(), 4);
+        assert!(
+            matches!(&results[2], Ok(p) if matches!(p.log_type, LogType::Rfc5424Log) && matches!(p.entry, OwnedLogEntry::Rfc5424Log(_)))
+        );
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn fallback_reports_preferred_error() {
+        let err =
+            parse_with_fallback(&[LogType::CommonLog, LogType::Rfc5424Log], "foo bar").unwrap_err();
+        assert_eq!(err, parse(LogType::CommonLog, "foo bar").unwrap_err());
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let log_types = [LogType::CommonLog, LogType::Rfc5424Log];
+        let sequential: Vec<_> = LogReader::with_fallback(Cursor::new(INPUT), log_types.to_vec())
+            .map(|r| r.map(|p| p.line_number).map_err(|e| e.to_string()))
+            .collect();
+        for threads in [1, 2, 3, 8] {
+            let parallel: Vec<_> = parse_parallel(&log_types, INPUT, threads)
+                .into_iter()
+                .map(|r| r.map(|p| p.line_number).map_err(|e| e.to_string()))
+                .collect();
+            assert_eq!(parallel, sequential, "{} threads", threads);
+        }
+        assert!(parse_parallel(&log_types, "", 4).is_empty());
+    }
+}
@@ -1,19 +1,591 @@
 pub mod structured_logging {
-    use access_log_parser::{LogEntry, LogType};
+    use access_log_parser::{AccessLogError, LogType};
+    use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+    use once_cell::sync::Lazy;
+    use regex::Regex;
+    use std::str::FromStr;
     use serde_json::{json, Value};
-    use std::collections::{HashMap, HashSet};
-    use std::sync::Mutex;
+    use std::borrow::Cow;
+    use std::collections::{HashMap, HashSet, VecDeque};
+    use flate2::read::MultiGzDecoder;
+    use std::fs::{self, File, OpenOptions};
+    use std::path::{Path, PathBuf};
+    use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use tauri::{Emitter, Manager};
+    use tauri_plugin_notification::NotificationExt;
+    use std::time::{Duration, Instant};
     use uuid::Uuid;
     use tracing::{info, warn, error};
+    use super::query::{self, QueryError, QueryTarget};
+    use super::patterns::{self, Cluster, TemplateMiner};
 
     static STRUCTURED_LOGGING_SESSIONS: Mutex<Option<HashMap<String, StructuredLoggingSession>>> =
         Mutex::new(None);
 
     #[derive(Debug)]
     pub struct StructuredLoggingSession {
-        entries: Vec<StructuredLogEntry>,
+        entries: VecDeque<StructuredLogEntry>,
+        /// Sequence number of `entries[0]`; grows as old entries are evicted.
+        first_seq: usize,
+        /// Dotted paths of every leaf value seen in the retained entries.
         columns: Vec<String>,
+        /// Number of retained entries carrying each column, per value type.
+        column_types: HashMap<String, HashMap<ColumnType, usize>>,
         facets: Vec<Facet>,
+        /// Inverted index per facet property: facet value key -> entry sequence
+        /// numbers in ascending order.
+        facet_index: HashMap<String, HashMap<String, VecDeque<usize>>>,
+        /// Message templates the entries' `pattern_id`s refer to.
+        patterns: TemplateMiner,
+        view_cache: Option<ViewCache>,
+        retention: RetentionPolicy,
+        /// Approximate size of the retained raw log lines.
+        bytes: usize,
+        eviction_stats: EvictionStats,
+        multiline: MultilineRules,
+        /// Trailing text of the last chunk that didn't end in a newline yet.
+        pending: String,
+        watches: Vec<Watch>,
+        /// Query and sorting the session was last viewed with.
+        view_state: ViewState,
+        /// Set when the session is saved to disk.
+        storage: Option<SessionStorage>,
+    }
+
+    #[derive(Debug, serde::Serialize)]
+    pub struct StructuredLoggingError {
+        message: String,
+    }
+
+    impl From<regex::Error> for StructuredLoggingError {
+        fn from(error: regex::Error) -> Self {
+            error!("Invalid pattern: {:?}", error);
+            return StructuredLoggingError {
+                message: error.to_string(),
+            };
+        }
+    }
+
+    impl From<std::io::Error> for StructuredLoggingError {
+        fn from(error: std::io::Error) -> Self {
+            error!("I/O error in structured logging session: {:?}", error);
+            return StructuredLoggingError {
+                message: error.to_string(),
+            };
+        }
+    }
+
+    impl From<tauri::Error> for StructuredLoggingError {
+        fn from(error: tauri::Error) -> Self {
+            error!("Tauri error in structured logging session: {:?}", error);
+            return StructuredLoggingError {
+                message: error.to_string(),
+            };
+        }
+    }
+
+    impl From<serde_json::Error> for StructuredLoggingError {
+        fn from(error: serde_json::Error) -> Self {
+            error!("Invalid saved structured logging session: {:?}", error);
+            return StructuredLoggingError {
+                message: error.to_string(),
+            };
+        }
+    }
+
+    impl From<QueryError> for StructuredLoggingError {
+        fn from(error: QueryError) -> Self {
+            return StructuredLoggingError {
+                message: format!("Invalid query: {}", error),
+            };
+        }
+    }
+
+    impl StructuredLoggingError {
+        fn session_not_found(session_id: &str) -> Self {
+            return StructuredLoggingError {
+                message: format!("Structured logging session not found: {}", session_id),
+            };
+        }
+    }
+
+    /// Controls which lines are merged into the previous entry instead of
+    /// becoming entries of their own, e.g. the frames of a stack trace.
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+    pub struct MultilineConfig {
+        enabled: bool,
+        /// Lines starting with a space or tab continue the previous entry.
+        leading_whitespace: bool,
+        /// Regular expressions marking a line as a continuation.
+        patterns: Vec<String>,
+        /// Lines that don't start with a timestamp continue the previous entry.
+        require_timestamp: bool,
+    }
+
+    impl Default for MultilineConfig {
+        fn default() -> Self {
+            MultilineConfig {
+                enabled: true,
+                leading_whitespace: true,
+                patterns: vec![
+                    r"^\s*at\s".to_string(),
+                    r"^\s*\.\.\. \d+ (more|common frames omitted)".to_string(),
+                    r"^Caused by:".to_string(),
+                    r"^Traceback \(most recent call last\)".to_string(),
+                ],
+                require_timestamp: false,
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct MultilineRules {
+        config: MultilineConfig,
+        patterns: Vec<Regex>,
+    }
+
+    impl MultilineRules {
+        fn new(config: MultilineConfig) -> Result<Self, regex::Error> {
+            let patterns = config
+                .patterns
+                .iter()
+                .map(|p| Regex::new(p))
+                .collect::<Result<Vec<Regex>, regex::Error>>()?;
+            Ok(MultilineRules { config, patterns })
+        }
+
+        /// JSON records (lines starting with `{`) and blank lines are never
+        /// continuations.
+        fn is_continuation(&self, line: &str) -> bool {
+            if !self.config.enabled || line.trim().is_empty() || line.starts_with('{') {
+                return false;
+            }
+            (self.config.leading_whitespace && line.starts_with([' ', '\t']))
+                || self.patterns.iter().any(|p| p.is_match(line))
+                || (self.config.require_timestamp && !TIMESTAMP_PREFIX.is_match(line))
+        }
+    }
+
+    /// ISO 8601 dates, klog headers and syslog dates at the start of a line.
+    static TIMESTAMP_PREFIX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^(\[?\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}|[IWEF]\d{4} |[A-Z][a-z]{2} [ \d]\d )")
+            .unwrap()
+    });
+
+    /// Limits after which the oldest entries of a session are evicted. Age is
+    /// measured from when an entry was received. Nothing is evicted unless a
+    /// limit is set.
+    #[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+    pub struct RetentionPolicy {
+        max_entries: Option<usize>,
+        max_bytes: Option<usize>,
+        max_age_seconds: Option<u64>,
+    }
+
+    #[derive(Clone, Debug, Default, serde::Serialize)]
+    pub struct EvictionStats {
+        evicted_entries: u64,
+        evicted_bytes: u64,
+    }
+
+    /// Fires when more than `threshold` entries matching `query` arrive within
+    /// `window_seconds`, e.g. more than 5 `level:error` in a minute.
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+    pub struct WatchRule {
+        name: String,
+        query: String,
+        threshold: usize,
+        window_seconds: u64,
+    }
+
+    #[derive(Debug)]
+    struct Watch {
+        rule: WatchRule,
+        query: Option<query::Query>,
+        /// Arrival times of the matches within the window, oldest first.
+        hits: VecDeque<Instant>,
+        /// A rule fires at most once per window.
+        fired_at: Option<Instant>,
+    }
+
+    impl Watch {
+        fn new(rule: WatchRule) -> Result<Self, QueryError> {
+            let query = query::parse(&rule.query)?;
+            Ok(Watch {
+                rule,
+                query,
+                hits: VecDeque::new(),
+                fired_at: None,
+            })
+        }
+
+        /// Records the matching entries among `entries` and returns an alert if
+        /// the rule's threshold is now exceeded.
+        fn check<'a>(
+            &mut self,
+            entries: impl Iterator<Item = &'a StructuredLogEntry>,
+            now: Instant,
+        ) -> Option<WatchAlert> {
+            let window = Duration::from_secs(self.rule.window_seconds);
+            let mut last_match = None;
+            for entry in entries {
+                if self.query.as_ref().is_none_or(|query| query.matches(entry)) {
+                    self.hits.push_back(entry.received_at);
+                    last_match = Some(entry);
+                }
+            }
+            while self.hits.front().is_some_and(|hit| now.duration_since(*hit) > window) {
+                self.hits.pop_front();
+            }
+
+            let last_match = last_match?;
+            if self.hits.len() <= self.rule.threshold
+                || self.fired_at.is_some_and(|fired| now.duration_since(fired) <= window)
+            {
+                return None;
+            }
+            self.fired_at = Some(now);
+            Some(WatchAlert {
+                rule: self.rule.clone(),
+                count: self.hits.len(),
+                entry: last_match.clone(),
+            })
+        }
+    }
+
+    /// Payload of the `structured_logging_alert_{session_id}` event.
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct WatchAlert {
+        rule: WatchRule,
+        /// Matches within the window when the rule fired.
+        count: usize,
+        /// The latest matching entry.
+        entry: StructuredLogEntry,
+    }
+
+    /// The filtered and sorted entry indices of the last requested view, so
+    /// paging through the same view doesn't re-run the filter and sort.
+    #[derive(Debug)]
+    struct ViewCache {
+        search_query: String,
+        /// `search_query` parsed, for highlighting the returned entries.
+        query: Option<query::Query>,
+        sorting: Vec<SortingState>,
+        from: Option<i64>,
+        to: Option<i64>,
+        indices: Vec<usize>,
+        /// Number of leading entries `indices` was computed over; entries
+        /// appended after them are merged in by `extend_view`.
+        len: usize,
+    }
+
+    impl StructuredLoggingSession {
+        /// Must be called whenever facet selections or already viewed entries
+        /// change. Appended and evicted entries are kept up with instead.
+        fn invalidate_view(&mut self) {
+            self.view_cache = None;
+        }
+
+        /// Merges the entries from position `start` onwards into the cached
+        /// view, keeping it sorted. A cache that doesn't end at `start` is
+        /// dropped.
+        fn extend_view(&mut self, start: usize) {
+            let Some(mut view) = self.view_cache.take() else {
+                return;
+            };
+            if view.len != start {
+                return;
+            }
+
+            let mut added =
+                get_filtered_indices(self, view.query.as_ref(), view.from, view.to, start);
+            apply_sorting(&self.entries, &mut added, &view.sorting);
+            if !added.is_empty() {
+                let keys = |position: usize| sort_keys(&self.entries[position], &view.sorting);
+                let mut merged = Vec::with_capacity(view.indices.len() + added.len());
+                let mut rest = &view.indices[..];
+                for position in added {
+                    // After the viewed entries it ties with, as a full sort would
+                    let key = keys(position);
+                    let at = rest.partition_point(|index| {
+                        compare_sort_keys(&keys(*index), &key, &view.sorting).is_le()
+                    });
+                    merged.extend_from_slice(&rest[..at]);
+                    merged.push(position);
+                    rest = &rest[at..];
+                }
+                merged.extend_from_slice(rest);
+                view.indices = merged;
+            }
+            view.len = self.entries.len();
+            self.view_cache = Some(view);
+        }
+
+        /// Adds the entries from position `start` onwards to the columns and
+        /// every facet index.
+        fn index_entries(&mut self, start: usize) {
+            for entry in self.entries.range_mut(start..) {
+                entry.pattern_id = Some(self.patterns.add(entry.message()));
+            }
+            for entry in self.entries.range(start..) {
+                self.bytes += entry.content.len();
+                for_each_leaf(&entry.data, &mut |path, value| {
+                    let types = match self.column_types.get_mut(path) {
+                        Some(types) => types,
+                        None => {
+                            self.columns.push(path.to_string());
+                            self.column_types.entry(path.to_string()).or_default()
+                        }
+                    };
+                    *types.entry(ColumnType::of(value)).or_insert(0) += 1;
+                });
+            }
+            for facet in self.facets.iter_mut() {
+                let index = self.facet_index.entry(facet.property.clone()).or_default();
+                index_facet(facet, index, &self.entries, self.first_seq, start);
+            }
+            self.extend_view(start);
+        }
+
+        /// Checks the watch rules against the entries from position `start`
+        /// onwards.
+        fn check_watches(&mut self, start: usize) -> Vec<WatchAlert> {
+            let now = Instant::now();
+            self.watches
+                .iter_mut()
+                .filter_map(|watch| watch.check(self.entries.range(start..), now))
+                .collect()
+        }
+
+        /// Evicts the oldest entries until the retention policy is satisfied.
+        fn apply_retention(&mut self) {
+            let now = Instant::now();
+            let max_age = self.retention.max_age_seconds.map(Duration::from_secs);
+            let mut evicted = 0;
+
+            while let Some(oldest) = self.entries.front() {
+                let over_limit = self
+                    .retention
+                    .max_entries
+                    .is_some_and(|max| self.entries.len() > max)
+                    || self.retention.max_bytes.is_some_and(|max| self.bytes > max)
+                    || max_age.is_some_and(|max| now.duration_since(oldest.received_at) > max);
+                if !over_limit {
+                    break;
+                }
+
+                let entry = self.entries.pop_front().unwrap();
+                self.unindex_entry(&entry, self.first_seq);
+                self.first_seq += 1;
+                self.eviction_stats.evicted_entries += 1;
+                self.eviction_stats.evicted_bytes += entry.content.len() as u64;
+                evicted += 1;
+            }
+
+            if evicted > 0 {
+                info!("Evicted {} entries from structured logging session", evicted);
+                match self.view_cache.as_mut() {
+                    Some(view) if view.len >= evicted => {
+                        view.indices.retain(|index| *index >= evicted);
+                        for index in view.indices.iter_mut() {
+                            *index -= evicted;
+                        }
+                        view.len -= evicted;
+                    }
+                    _ => self.invalidate_view(),
+                }
+            }
+        }
+
+        /// Appends continuation lines to the last entry. Its content, and with
+        /// it its size and possibly its fields, change, so it is re-indexed.
+        fn append_to_last_entry(&mut self, lines: &[&str]) {
+            let Some(mut entry) = self.entries.pop_back() else {
+                return;
+            };
+            let position = self.entries.len();
+            self.unindex_entry(&entry, self.first_seq + position);
+            if let Some(view) = self.view_cache.as_mut().filter(|view| view.len == position + 1) {
+                view.indices.retain(|index| *index != position);
+                view.len = position;
+            }
+            for line in lines {
+                entry.append_line(line);
+            }
+            self.entries.push_back(entry);
+            self.index_entries(self.entries.len() - 1);
+        }
+
+        /// Undoes `index_entries` for an entry that was removed from either
+        /// end of `entries`, where it had sequence number `seq`.
+        fn unindex_entry(&mut self, entry: &StructuredLogEntry, seq: usize) {
+            self.bytes -= entry.content.len();
+            if let Some(pattern_id) = entry.pattern_id {
+                self.patterns.remove(pattern_id);
+            }
+
+            for_each_leaf(&entry.data, &mut |path, value| {
+                let Some(types) = self.column_types.get_mut(path) else {
+                    return;
+                };
+                let kind = ColumnType::of(value);
+                if let Some(count) = types.get_mut(&kind) {
+                    *count -= 1;
+                    if *count == 0 {
+                        types.remove(&kind);
+                    }
+                }
+                if types.is_empty() {
+                    self.column_types.remove(path);
+                    self.columns.retain(|c| c != path);
+                }
+            });
+
+            for facet in self.facets.iter_mut() {
+                let Some(value) = entry.get(&facet.property) else {
+                    continue;
+                };
+                let key = serde_json::to_string(&value).unwrap();
+                let Some(index) = self.facet_index.get_mut(&facet.property) else {
+                    continue;
+                };
+                // Being at an end of `entries`, the entry is also at that end
+                // of its value's list
+                let total = match index.get_mut(&key) {
+                    Some(seqs) => {
+                        if seqs.front() == Some(&seq) {
+                            seqs.pop_front();
+                        } else if seqs.back() == Some(&seq) {
+                            seqs.pop_back();
+                        }
+                        seqs.len() as u32
+                    }
+                    None => 0,
+                };
+                if total == 0 {
+                    index.remove(&key);
+                    facet.values.retain(|v| v.value != key || v.filtered || v.excluded);
+                }
+                if let Some(facet_value) = facet.values.iter_mut().find(|v| v.value == key) {
+                    facet_value.total = total;
+                }
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ColumnType {
+        Null,
+        Bool,
+        Number,
+        String,
+        Array,
+    }
+
+    impl ColumnType {
+        fn of(value: &Value) -> ColumnType {
+            match value {
+                Value::Null => ColumnType::Null,
+                Value::Bool(_) => ColumnType::Bool,
+                Value::Number(_) => ColumnType::Number,
+                Value::String(_) => ColumnType::String,
+                // Objects are never leaves
+                Value::Array(_) | Value::Object(_) => ColumnType::Array,
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct ColumnInfo {
+        path: String,
+        count: usize,
+        types: Vec<ColumnType>,
+    }
+
+    /// Calls `f` with the dotted path of every non-object value nested in
+    /// `value`. Arrays are leaves.
+    fn for_each_leaf(value: &Value, f: &mut impl FnMut(&str, &Value)) {
+        fn walk(value: &Value, path: &mut String, f: &mut impl FnMut(&str, &Value)) {
+            match value {
+                Value::Object(map) => {
+                    for (key, value) in map {
+                        let len = path.len();
+                        if len > 0 {
+                            path.push('.');
+                        }
+                        path.push_str(key);
+                        walk(value, path, f);
+                        path.truncate(len);
+                    }
+                }
+                leaf => f(path, leaf),
+            }
+        }
+
+        if value.is_object() {
+            walk(value, &mut String::new(), f);
+        }
+    }
+
+    fn new_session(entries: VecDeque<StructuredLogEntry>) -> StructuredLoggingSession {
+        let mut session = StructuredLoggingSession {
+            entries,
+            first_seq: 0,
+            columns: Vec::new(),
+            column_types: HashMap::new(),
+            facets: Vec::new(),
+            facet_index: HashMap::new(),
+            patterns: TemplateMiner::default(),
+            view_cache: None,
+            retention: RetentionPolicy::default(),
+            bytes: 0,
+            eviction_stats: EvictionStats::default(),
+            multiline: MultilineRules::new(MultilineConfig::default()).unwrap(),
+            pending: String::new(),
+            watches: Vec::new(),
+            view_state: ViewState::default(),
+            storage: None,
+        };
+        session.index_entries(0);
+        session.apply_retention();
+        session
+    }
+
+    /// Indexes `entries[start..]` for a single facet and refreshes the totals
+    /// of the values that were touched, so a batch costs O(batch).
+    fn index_facet(
+        facet: &mut Facet,
+        index: &mut HashMap<String, VecDeque<usize>>,
+        entries: &VecDeque<StructuredLogEntry>,
+        first_seq: usize,
+        start: usize,
+    ) {
+        let mut touched = HashSet::new();
+        for (position, entry) in entries.iter().enumerate().skip(start) {
+            if let Some(value) = entry.get(&facet.property) {
+                let key = serde_json::to_string(&value).unwrap();
+                index.entry(key.clone()).or_default().push_back(first_seq + position);
+                touched.insert(key);
+            }
+        }
+
+        for key in touched {
+            let total = index[&key].len() as u32;
+            if let Some(facet_value) = facet.values.iter_mut().find(|v| v.value == key) {
+                facet_value.total = total;
+            } else {
+                facet.values.push(FacetValue {
+                    value: key,
+                    filtered: false,
+                    excluded: false,
+                    total,
+                });
+            }
+        }
     }
 
     #[derive(Clone, Debug, serde::Serialize)]
@@ -22,70 +594,336 @@ pub mod structured_logging {
         content: String,
         timestamp: String,
         data: serde_json::Value,
+        /// Milliseconds since the Unix epoch, parsed from `timestamp` or, failing
+        /// that, from a timestamp field in `data`.
+        time: Option<i64>,
+        /// Canonical severity, derived regardless of the log format.
+        level: Level,
+        /// Id of the message template the entry was grouped into.
+        pattern_id: Option<usize>,
+        #[serde(skip)]
+        received_at: Instant,
     }
 
-    #[derive(Clone, Debug, serde::Serialize)]
+    impl StructuredLogEntry {
+        fn new(content: String, timestamp: String, data: Value) -> Self {
+            let time = parse_time(&timestamp)
+                .or_else(|| find_time_in_data(&data))
+                .or_else(|| parse_klog_time(&content, Utc::now()));
+            let level = detect_level(&content, &data);
+            StructuredLogEntry {
+                id: Uuid::new_v4(),
+                content,
+                timestamp,
+                data,
+                time,
+                level,
+                pattern_id: None,
+                received_at: Instant::now(),
+            }
+        }
+
+        /// Appends a continuation line, e.g. a stack frame.
+        fn append_line(&mut self, line: &str) {
+            self.content.push('\n');
+            self.content.push_str(line);
+            // Only plain text records carry the whole line as their message
+            if let Some(obj) = self.data.as_object_mut() {
+                if obj.len() == 1 {
+                    if let Some(Value::String(message)) = obj.get_mut("message") {
+                        message.push('\n');
+                        message.push_str(line);
+                    }
+                }
+            }
+        }
+
+        /// The text templates are mined from: the message field of structured
+        /// records, otherwise the raw line. Only the first line is used so
+        /// stack traces don't split templates.
+        fn message(&self) -> &str {
+            let message = ["message", "msg", "log", "text"]
+                .iter()
+                .find_map(|field| self.data.get(field).and_then(Value::as_str))
+                .unwrap_or(&self.content);
+            message.lines().next().unwrap_or("")
+        }
+
+        /// Looks up a field by dotted path or JSON Pointer. `level` always
+        /// resolves to the canonical level rather than the raw value in `data`,
+        /// and `pattern_id` to the entry's message template.
+        fn get(&self, path: &str) -> Option<Cow<'_, Value>> {
+            if path == "level" {
+                return Some(Cow::Borrowed(self.level.as_value()));
+            }
+            if path == "pattern_id" {
+                return self.pattern_id.map(|id| Cow::Owned(json!(id)));
+            }
+            if path.starts_with('/') {
+                return self.data.pointer(path).map(Cow::Borrowed);
+            }
+            if let Some(value) = self.data.get(path) {
+                return Some(Cow::Borrowed(value));
+            }
+            let mut segments = path.split('.');
+            let first = segments.next()?;
+            if let Some(mut value) = self.data.get(first) {
+                for segment in segments {
+                    value = match value {
+                        Value::Object(map) => map.get(segment)?,
+                        Value::Array(values) => values.get(segment.parse::<usize>().ok()?)?,
+                        _ => return None,
+                    };
+                }
+                return Some(Cow::Borrowed(value));
+            }
+            None
+        }
+    }
+
+    /// Canonical log levels, ordered by severity.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Level {
+        Unknown,
+        Trace,
+        Debug,
+        Info,
+        Warn,
+        Error,
+        Fatal,
+    }
+
+    static LEVEL_VALUES: Lazy<[Value; 7]> = Lazy::new(|| {
+        ["unknown", "trace", "debug", "info", "warn", "error", "fatal"].map(|l| json!(l))
+    });
+
+    impl Level {
+        /// Parses the many spellings of a level name, including single letter
+        /// (klog style) and syslog severity names.
+        pub fn from_name(name: &str) -> Option<Level> {
+            let level = match name.trim().to_lowercase().as_str() {
+                "t" | "trc" | "trace" | "finest" | "finer" => Level::Trace,
+                "d" | "dbg" | "debug" | "fine" => Level::Debug,
+                "i" | "inf" | "info" | "information" | "informational" | "notice" => Level::Info,
+                "w" | "wrn" | "warn" | "warning" => Level::Warn,
+                "e" | "err" | "eror" | "error" | "severe" => Level::Error,
+                "f" | "ftl" | "fatal" | "panic" | "dpanic" | "crit" | "critical" | "alert"
+                | "emerg" | "emergency" => Level::Fatal,
+                _ => return None,
+            };
+            Some(level)
+        }
+
+        /// Bunyan and pino numeric levels.
+        fn from_number(level: f64) -> Level {
+            match level as i64 {
+                i64::MIN..=10 => Level::Trace,
+                11..=20 => Level::Debug,
+                21..=30 => Level::Info,
+                31..=40 => Level::Warn,
+                41..=50 => Level::Error,
+                _ => Level::Fatal,
+            }
+        }
+
+        fn from_status(status: u64) -> Option<Level> {
+            match status {
+                100..=399 => Some(Level::Info),
+                400..=499 => Some(Level::Warn),
+                500..=599 => Some(Level::Error),
+                _ => None,
+            }
+        }
+
+        pub(crate) fn as_value(&self) -> &'static Value {
+            &LEVEL_VALUES[*self as usize]
+        }
+    }
+
+    const LEVEL_FIELDS: [&str; 6] =
+        ["level", "lvl", "severity", "loglevel", "log_level", "log.level"];
+    const STATUS_FIELDS: [&str; 4] = ["status_code", "status", "http_status", "statusCode"];
+
+    fn detect_level(content: &str, data: &Value) -> Level {
+        for field in LEVEL_FIELDS {
+            let value = data
+                .get(field)
+                .or_else(|| field.split_once('.').and_then(|(a, b)| data.get(a)?.get(b)));
+            match value {
+                Some(Value::String(name)) => {
+                    if let Some(level) = Level::from_name(name) {
+                        return level;
+                    }
+                }
+                Some(Value::Number(n)) => return Level::from_number(n.as_f64().unwrap_or(0.0)),
+                _ => {}
+            }
+        }
+
+        // klog header, e.g. "I0412 10:00:00.000000       1 main.go:42] ..."
+        let bytes = content.as_bytes();
+        if bytes.len() > 5
+            && b"IWEF".contains(&bytes[0])
+            && bytes[1..5].iter().all(u8::is_ascii_digit)
+        {
+            return Level::from_name(&content[..1]).unwrap();
+        }
+
+        for field in STATUS_FIELDS {
+            let status = data.get(field).and_then(Value::as_u64);
+            if let Some(level) = status.and_then(Level::from_status) {
+                return level;
+            }
+        }
+
+        // An upper-case level word among the first few words of the line
+        for word in content.split_whitespace().take(4) {
+            let word = word.trim_matches(|c: char| !c.is_ascii_alphabetic());
+            if word.len() > 2 && word.chars().all(|c| c.is_ascii_uppercase()) {
+                if let Some(level) = Level::from_name(word) {
+                    return level;
+                }
+            }
+        }
+
+        Level::Unknown
+    }
+
+    /// Field names that commonly carry the event time in structured logs.
+    const TIME_FIELDS: [&str; 6] = ["timestamp", "@timestamp", "time", "ts", "date", "datetime"];
+
+    fn parse_time(input: &str) -> Option<i64> {
+        let input = input.trim();
+        if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+            return Some(time.timestamp_millis());
+        }
+        // Common/combined access log format, e.g. 10/Oct/2000:13:55:36 -0700
+        if let Ok(time) = DateTime::parse_from_str(input, "%d/%b/%Y:%H:%M:%S %z") {
+            return Some(time.timestamp_millis());
+        }
+        // Timestamps without an offset are taken to be UTC
+        for format in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+            if let Ok(time) = NaiveDateTime::parse_from_str(input, format) {
+                return Some(time.and_utc().timestamp_millis());
+            }
+        }
+        None
+    }
+
+    static KLOG_TIME: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^[IWEF](\d{4} \d{2}:\d{2}:\d{2}(?:\.\d+)?) ").unwrap()
+    });
+
+    /// The time in a klog header, e.g. "I0412 10:00:00.000000 ...". The header
+    /// has no year, so the one that puts it closest to `now` is assumed.
+    fn parse_klog_time(content: &str, now: DateTime<Utc>) -> Option<i64> {
+        let time = &KLOG_TIME.captures(content)?[1];
+        [now.year() - 1, now.year(), now.year() + 1]
+            .into_iter()
+            .filter_map(|year| {
+                let time = format!("{}{}", year, time);
+                NaiveDateTime::parse_from_str(&time, "%Y%m%d %H:%M:%S%.f").ok()
+            })
+            .map(|time| time.and_utc().timestamp_millis())
+            .min_by_key(|time| (time - now.timestamp_millis()).abs())
+    }
+
+    fn find_time_in_data(data: &Value) -> Option<i64> {
+        TIME_FIELDS.iter().find_map(|field| match data.get(*field)? {
+            Value::String(s) => parse_time(s),
+            // Epoch seconds (possibly fractional), milliseconds, microseconds
+            // or nanoseconds, told apart by magnitude
+            Value::Number(n) => {
+                let n = n.as_f64()?;
+                let millis = match n.abs() {
+                    abs if abs < 1e11 => n * 1e3,
+                    abs if abs < 1e14 => n,
+                    abs if abs < 1e17 => n / 1e3,
+                    _ => n / 1e6,
+                };
+                Some(millis as i64)
+            }
+            _ => None,
+        })
+    }
+
+    /// How a facet with selected values combines with the other facets.
+    /// Entries must match every `AND` facet and, if any `OR` facet has a
+    /// selection, at least one of those. Within a facet the selected values
+    /// are always OR-ed.
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
     pub enum MatchType {
         AND,
         OR,
     }
 
-    #[derive(Clone, Debug, serde::Serialize)]
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
     pub struct Facet {
         property: String,
         match_type: MatchType,
         values: Vec<FacetValue>,
     }
 
-    #[derive(Clone, Debug, serde::Serialize)]
+    /// A value is either selected (`filtered`), excluded or neither. Entries
+    /// with an excluded value are hidden regardless of the match types.
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
     pub struct FacetValue {
         value: String,
         filtered: bool,
+        #[serde(default)]
+        excluded: bool,
         total: u32,
     }
 
     #[derive(Clone, Debug, serde::Serialize)]
     pub struct FilteredLogResult {
         entries: Vec<StructuredLogEntry>,
+        /// What the search query matched in each of `entries`, in the same
+        /// order; empty without a query.
+        matches: Vec<query::Highlights>,
         total: u32,
+        filtered: u32,
+        offset: u32,
+        evicted: u64,
     }
 
-    #[derive(Clone, Debug, serde::Deserialize)]
+    #[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
     pub struct SortingState {
         id: String,
         desc: bool,
     }
 
+    /// The query and sorting of a session's view. Kept by the session so a
+    /// saved session or an applied log view can restore them.
+    #[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+    pub struct ViewState {
+        search_query: String,
+        sorting: Vec<SortingState>,
+    }
+
     #[tauri::command]
     pub async fn start_structured_logging_session(initial_data: Vec<String>) -> String {
         info!("Starting structured logging session");
         let session_id = Uuid::new_v4().to_string();
 
-        if STRUCTURED_LOGGING_SESSIONS.lock().unwrap().is_none() {
-            *STRUCTURED_LOGGING_SESSIONS.lock().unwrap() = Some(HashMap::new());
-        }
-
         STRUCTURED_LOGGING_SESSIONS
             .lock()
             .unwrap()
-            .as_mut()
-            .unwrap()
+            .get_or_insert_with(HashMap::new)
             .insert(
                 session_id.clone(),
-                StructuredLoggingSession {
-                    entries: initial_data
+                new_session(
+                    initial_data
                         .into_iter()
-                        .map(|d| StructuredLogEntry {
-                            id: Uuid::new_v4(),
-                            content: d.clone(),
-                            timestamp: d.splitn(2, ' ').next().unwrap_or("").to_string(),
-                            data: serde_json::from_str(d.splitn(2, ' ').nth(1).unwrap_or(""))
-                                .unwrap_or_else(|_| serde_json::Value::String(d)),
+                        .map(|d| {
+                            let timestamp = d.splitn(2, ' ').next().unwrap_or("").to_string();
+                            let data = serde_json::from_str(d.splitn(2, ' ').nth(1).unwrap_or(""))
+                                .unwrap_or_else(|_| serde_json::Value::String(d.clone()));
+                            StructuredLogEntry::new(d, timestamp, data)
                         })
                         .collect(),
-                    columns: Vec::new(),
-                    facets: Vec::new(),
-                },
+                ),
             );
 
         return session_id;
@@ -101,585 +939,4446 @@ pub mod structured_logging {
             .unwrap()
             .get_mut(&session_id)
         {
+            session.first_seq += session.entries.len();
             session.entries.clear();
+            session.columns.clear();
+            session.column_types.clear();
+            session.bytes = 0;
+            session.pending.clear();
+            session.facet_index.clear();
+            // Templates are kept so selected `pattern_id`s stay meaningful
+            session.patterns.clear_counts();
+            // Keep the selected facet values around for the new data
+            for facet in session.facets.iter_mut() {
+                facet.values.retain(|v| v.filtered || v.excluded);
+                for facet_value in facet.values.iter_mut() {
+                    facet_value.total = 0;
+                }
+            }
+            session.invalidate_view();
         }
     }
 
     #[tauri::command]
     pub async fn end_structured_logging_session(session_id: String) {
         info!("Ending structured logging session: {}", session_id);
-        STRUCTURED_LOGGING_SESSIONS
+        // Nobody listens for alerts of a session that is going away
+        flush_pending(&session_id);
+        let session = STRUCTURED_LOGGING_SESSIONS
             .lock()
             .unwrap()
             .as_mut()
             .unwrap()
             .remove(&session_id);
+        if let Some(mut session) = session {
+            session.save_entries(true);
+            // Picks up the latest view state, which isn't saved as it changes
+            session.save_meta();
+        }
     }
 
+    /// Where a saved session lives on disk: `meta.json` with its name, facets
+    /// and settings, and `entries.ndjson`, an append-only log of its entries.
     #[derive(Debug)]
-    enum ExtractedContent {
-        Json(Value),
-        Text(String),
+    struct SessionStorage {
+        dir: PathBuf,
+        name: String,
+        created_at: i64,
+        entries: BufWriter<File>,
+        /// Sequence number of the next entry to append.
+        next_seq: usize,
     }
 
-    fn extract_content(input: &str) -> Vec<ExtractedContent> {
-        let mut extracted_content = Vec::new();
-        let mut start_index = None;
-        let mut brace_count = 0;
-        let mut last_end_index = 0;
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct SessionMeta {
+        name: String,
+        created_at: i64,
+        facets: Vec<Facet>,
+        multiline: MultilineConfig,
+        retention: RetentionPolicy,
+        #[serde(default)]
+        watch_rules: Vec<WatchRule>,
+        #[serde(default)]
+        view_state: ViewState,
+    }
 
-        for (i, c) in input.char_indices() {
-            match c {
-                '{' => {
-                    if brace_count == 0 && i > last_end_index {
-                        // Capture text before JSON
-                        extracted_content
-                            .push(ExtractedContent::Text(input[last_end_index..i].to_string()));
-                    }
-                    if start_index.is_none() {
-                        start_index = Some(i);
-                    }
-                    brace_count += 1;
-                }
-                '}' => {
-                    if brace_count > 0 {
-                        brace_count -= 1;
-                    }
-                    if brace_count == 0 {
-                        if let Some(start) = start_index {
-                            let json_str = &input[start..=i];
-                            if let Ok(value) = serde_json::from_str::<Value>(json_str) {
-                                extracted_content.push(ExtractedContent::Json(value));
-                            }
-                            last_end_index = i + 1;
-                            start_index = None; // Reset for the next potential JSON object
-                        }
-                    }
-                }
-                _ => {}
-            }
-        }
+    #[derive(serde::Serialize)]
+    struct SavedEntryRef<'a> {
+        timestamp: &'a str,
+        content: &'a str,
+        data: &'a Value,
+    }
 
-        // Capture any remaining text after the last JSON object
-        if last_end_index < input.len() {
-            extracted_content.push(ExtractedContent::Text(input[last_end_index..].to_string()));
-        }
+    #[derive(serde::Deserialize)]
+    struct SavedEntry {
+        timestamp: String,
+        content: String,
+        data: Value,
+    }
 
-        extracted_content
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct SavedSessionInfo {
+        id: String,
+        name: String,
+        created_at: i64,
+        size_bytes: u64,
+        open: bool,
     }
 
-    #[tauri::command]
-    pub async fn add_data_to_structured_logging_session(session_id: String, data: String) {
-        info!("Adding data to structured logging session: {}", session_id);
-        // split the data by newline if there's any
-        let data = data.split("\n").collect::<Vec<&str>>();
+    const META_FILE: &str = "meta.json";
+    const ENTRIES_FILE: &str = "entries.ndjson";
 
-        let parsed_records = data
-            .into_iter()
-            .flat_map(|d| {
-                let timestamp = d.splitn(2, ' ').next().unwrap_or("");
-                let data = d.splitn(2, ' ').nth(1).unwrap_or("");
-                extract_content(data)
-                    .into_iter()
-                    .filter_map(|content| match content {
-                        ExtractedContent::Json(json) => {
-                            update_columns_for_logging_session(session_id.clone(), &json);
-                            Some(StructuredLogEntry {
-                                id: Uuid::new_v4(),
-                                content: data.to_string(),
-                                timestamp: timestamp.to_string(),
-                                data: json,
-                            })
-                        }
-                        ExtractedContent::Text(text) => {
-                            let log_record = parse_log_record(session_id.clone(), &text);
-                            Some(StructuredLogEntry {
-                                id: Uuid::new_v4(),
-                                content: data.to_string(),
-                                timestamp: timestamp.to_string(),
-                                data: log_record,
-                            })
-                        }
-                    })
-            })
-            .collect::<Vec<StructuredLogEntry>>();
+    fn saved_sessions_dir(
+        app_handle: &tauri::AppHandle,
+    ) -> Result<PathBuf, StructuredLoggingError> {
+        Ok(app_handle.path().app_data_dir()?.join("log_sessions"))
+    }
 
-        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .get_mut(&session_id)
-        {
-            session.entries.extend(parsed_records);
+    /// Session ids double as directory names, so only accept what we generate.
+    fn saved_session_dir(sessions_dir: &Path, id: &str) -> Result<PathBuf, StructuredLoggingError> {
+        if Uuid::parse_str(id).is_err() {
+            return Err(StructuredLoggingError::session_not_found(id));
         }
+        Ok(sessions_dir.join(id))
+    }
 
-        update_unique_facet_values_for_logging_session(session_id);
+    /// Replaces `meta.json` in `dir` through a temporary file, so a crash
+    /// never leaves a truncated one behind.
+    fn write_meta(dir: &Path, meta: &SessionMeta) -> std::io::Result<()> {
+        let tmp = dir.join(format!("{}.tmp", META_FILE));
+        fs::write(&tmp, serde_json::to_vec_pretty(meta)?)?;
+        fs::rename(tmp, dir.join(META_FILE))
     }
 
-    fn parse_log_record(session_id: String, data: &str) -> serde_json::Value {
-        match serde_json::from_str(data) {
-            Ok(json) => {
-                update_columns_for_logging_session(session_id, &json);
-                return json;
+    impl StructuredLoggingSession {
+        fn meta(&self, storage: &SessionStorage) -> SessionMeta {
+            SessionMeta {
+                name: storage.name.clone(),
+                created_at: storage.created_at,
+                facets: self.facets.clone(),
+                multiline: self.multiline.config.clone(),
+                retention: self.retention.clone(),
+                watch_rules: self.watches.iter().map(|watch| watch.rule.clone()).collect(),
+                view_state: self.view_state.clone(),
             }
-            Err(_) => {}
         }
 
-        match access_log_parser::parse(LogType::CommonLog, data) {
-            Ok(clf_record) => match clf_record {
-                LogEntry::CommonLog(entry) => {
-                    let clf_json = serde_json::to_string(&entry);
-
-                    match clf_json {
-                        Ok(json) => {
-                            update_columns_for_logging_session(
-                                session_id,
-                                &serde_json::from_str(&json).unwrap(),
-                            );
-                            return serde_json::from_str(&json).unwrap();
-                        }
-                        Err(_) => {}
-                    }
-                }
-                _ => {}
-            },
-            Err(_) => {}
+        /// Rewrites `meta.json` of a saved session; a no-op for unsaved ones.
+        fn save_meta(&self) {
+            let Some(storage) = &self.storage else {
+                return;
+            };
+            if let Err(e) = write_meta(&storage.dir, &self.meta(storage)) {
+                error!("Error saving structured logging session meta: {:?}", e);
+            }
         }
 
-        match access_log_parser::parse(LogType::CombinedLog, data) {
-            Ok(cl_record) => match cl_record {
-                LogEntry::CombinedLog(entry) => {
-                    let cl_json = serde_json::to_string(&entry);
-
-                    match cl_json {
-                        Ok(json) => {
-                            update_columns_for_logging_session(
-                                session_id,
-                                &serde_json::from_str(&json).unwrap(),
-                            );
-                            return serde_json::from_str(&json).unwrap();
-                        }
-                        Err(_) => {}
-                    }
-                }
-                _ => {}
-            },
-            Err(_) => {}
+        /// Appends entries that aren't on disk yet to a saved session.
+        fn save_entries(&mut self, include_last: bool) {
+            let Some(storage) = &mut self.storage else {
+                return;
+            };
+            let end = self.first_seq + self.entries.len();
+            let end = if include_last { end } else { end.saturating_sub(1) };
+            let start = storage.next_seq.max(self.first_seq);
+            let result = (start..end)
+                .try_for_each(|seq| {
+                    let entry = &self.entries[seq - self.first_seq];
+                    let saved = SavedEntryRef {
+                        timestamp: &entry.timestamp,
+                        content: &entry.content,
+                        data: &entry.data,
+                    };
+                    serde_json::to_writer(&mut storage.entries, &saved)?;
+                    storage.entries.write_all(b"\n")
+                })
+                .and_then(|_| storage.entries.flush());
+            match result {
+                Ok(_) => storage.next_seq = storage.next_seq.max(end),
+                Err(e) => error!("Error saving structured logging session entries: {:?}", e),
+            }
         }
+    }
 
-        match access_log_parser::parse(LogType::GorouterLog, data) {
-            Ok(grl_record) => match grl_record {
-                LogEntry::GorouterLog(entry) => {
-                    let grl_json = serde_json::to_string(&entry);
-
-                    match grl_json {
-                        Ok(json) => {
-                            update_columns_for_logging_session(
-                                session_id,
-                                &serde_json::from_str(&json).unwrap(),
-                            );
-                            return serde_json::from_str(&json).unwrap();
-                        }
-                        Err(_) => {}
-                    }
-                }
-                _ => {}
-            },
-            Err(_) => {}
-        }
+    /// Saves a session to disk under the app data dir. From then on new
+    /// entries are appended and facet and settings changes are kept.
+    #[tauri::command]
+    pub async fn save_structured_logging_session(
+        app_handle: tauri::AppHandle,
+        session_id: String,
+        name: String,
+    ) -> Result<SavedSessionInfo, StructuredLoggingError> {
+        info!("Saving structured logging session: {}", session_id);
+        return save_session(&saved_sessions_dir(&app_handle)?, session_id, name);
+    }
 
-        match access_log_parser::parse(LogType::CloudControllerLog, data) {
-            Ok(ccl_record) => match ccl_record {
-                LogEntry::CloudControllerLog(entry) => {
-                    let ccl_json = serde_json::to_string(&entry);
+    fn save_session(
+        sessions_dir: &Path,
+        session_id: String,
+        name: String,
+    ) -> Result<SavedSessionInfo, StructuredLoggingError> {
+        let dir = saved_session_dir(sessions_dir, &session_id)?;
+        let mut sessions = STRUCTURED_LOGGING_SESSIONS.lock().unwrap();
+        let Some(session) = sessions.as_mut().unwrap().get_mut(&session_id) else {
+            return Err(StructuredLoggingError::session_not_found(&session_id));
+        };
 
-                    match ccl_json {
-                        Ok(json) => {
-                            update_columns_for_logging_session(
-                                session_id,
-                                &serde_json::from_str(&json).unwrap(),
-                            );
-                            return serde_json::from_str(&json).unwrap();
-                        }
-                        Err(_) => {}
-                    }
-                }
-                _ => {}
-            },
-            Err(_) => {}
+        if let Some(storage) = &mut session.storage {
+            storage.name = name;
+        } else {
+            fs::create_dir_all(&dir)?;
+            let entries = File::create(dir.join(ENTRIES_FILE))?;
+            session.storage = Some(SessionStorage {
+                dir: dir.clone(),
+                name,
+                created_at: chrono::Utc::now().timestamp_millis(),
+                entries: BufWriter::new(entries),
+                next_seq: session.first_seq,
+            });
         }
+        session.save_entries(true);
+        session.save_meta();
 
-        return json!({ "message": data });
+        let storage = session.storage.as_ref().unwrap();
+        return Ok(SavedSessionInfo {
+            id: session_id.clone(),
+            name: storage.name.clone(),
+            created_at: storage.created_at,
+            size_bytes: fs::metadata(dir.join(ENTRIES_FILE))?.len(),
+            open: true,
+        });
     }
 
-    fn update_columns_for_logging_session(session_id: String, record: &serde_json::Value) {
-        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .get_mut(&session_id)
-        {
-            if let Some(obj) = record.as_object() {
-                for key in obj.keys() {
-                    if !session.columns.contains(key) {
-                        session.columns.push(key.clone());
-                    }
-                }
-            }
-        }
+    #[tauri::command]
+    pub async fn list_saved_structured_logging_sessions(
+        app_handle: tauri::AppHandle,
+    ) -> Result<Vec<SavedSessionInfo>, StructuredLoggingError> {
+        info!("Listing saved structured logging sessions");
+        return list_saved_sessions(&saved_sessions_dir(&app_handle)?);
     }
 
-    #[tauri::command]
-    pub async fn add_facet_to_structured_logging_session(
-        session_id: String,
-        property: String,
-        match_type: String,
-    ) {
-        info!("Adding facet to structured logging session: {}", session_id);
-        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .get_mut(&session_id)
-        {
-            let match_type = match match_type.as_str() {
-                "AND" => MatchType::AND,
-                "OR" => MatchType::OR,
-                _ => MatchType::OR,
+    fn list_saved_sessions(dir: &Path) -> Result<Vec<SavedSessionInfo>, StructuredLoggingError> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let sessions = STRUCTURED_LOGGING_SESSIONS.lock().unwrap();
+        let mut saved = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Ok(meta) = fs::read(path.join(META_FILE)) else {
+                continue;
             };
-            session.facets.push(Facet {
-                property,
-                match_type: match_type,
-                values: Vec::new(),
+            let Ok(meta) = serde_json::from_slice::<SessionMeta>(&meta) else {
+                warn!("Skipping unreadable saved session: {:?}", path);
+                continue;
+            };
+            let id = path.file_name().unwrap().to_string_lossy().to_string();
+            saved.push(SavedSessionInfo {
+                open: sessions.as_ref().is_some_and(|s| s.contains_key(&id)),
+                id,
+                name: meta.name,
+                created_at: meta.created_at,
+                size_bytes: fs::metadata(path.join(ENTRIES_FILE)).map_or(0, |m| m.len()),
             });
         }
+        saved.sort_by_key(|s| std::cmp::Reverse(s.created_at));
 
-        update_unique_facet_values_for_logging_session(session_id);
+        return Ok(saved);
     }
 
+    /// Loads a saved session, restoring its facets (including selected values),
+    /// columns and settings. Returns the session id to use with the other
+    /// commands, which is the saved session's id.
     #[tauri::command]
-    pub async fn set_facet_match_type_for_structured_logging_session(
-        session_id: String,
-        property: String,
-        match_type: String,
-    ) {
-        info!("Setting facet match type for structured logging session: {}", session_id);
-        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .get_mut(&session_id)
-        {
-            for facet in session.facets.iter_mut() {
-                if facet.property == property {
-                    facet.match_type = match match_type.as_str() {
-                        "AND" => MatchType::AND,
-                        "OR" => MatchType::OR,
-                        _ => MatchType::OR,
-                    };
-                    break;
-                }
-            }
-        }
+    pub async fn open_saved_structured_logging_session(
+        app_handle: tauri::AppHandle,
+        id: String,
+    ) -> Result<String, StructuredLoggingError> {
+        info!("Opening saved structured logging session: {}", id);
+        return open_saved_session(&saved_sessions_dir(&app_handle)?, id);
     }
 
-    #[tauri::command]
-    pub async fn remove_facet_from_structured_logging_session(
-        session_id: String,
-        property: String,
-    ) {
-        info!("Removing facet from structured logging session: {}", session_id);
-        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .get_mut(&session_id)
-        {
-            session.facets.retain(|f| f.property != property);
+    fn open_saved_session(
+        sessions_dir: &Path,
+        id: String,
+    ) -> Result<String, StructuredLoggingError> {
+        let dir = saved_session_dir(sessions_dir, &id)?;
+        // Held until the session is inserted, so concurrent opens load it once
+        let mut sessions = STRUCTURED_LOGGING_SESSIONS.lock().unwrap();
+        let sessions = sessions.get_or_insert_with(HashMap::new);
+        if sessions.contains_key(&id) {
+            return Ok(id);
+        }
+
+        let meta: SessionMeta = serde_json::from_slice(&fs::read(dir.join(META_FILE))?)?;
+        let entries = read_saved_entries(&dir.join(ENTRIES_FILE))?;
+
+        let mut session = new_session(VecDeque::new());
+        session.multiline = MultilineRules::new(meta.multiline)?;
+        session.retention = meta.retention;
+        session.watches = meta
+            .watch_rules
+            .into_iter()
+            .map(Watch::new)
+            .collect::<Result<Vec<Watch>, QueryError>>()?;
+        session.view_state = meta.view_state;
+        for facet in meta.facets {
+            session.facet_index.insert(facet.property.clone(), HashMap::new());
+            session.facets.push(Facet {
+                values: facet.values.into_iter().filter(|v| v.filtered || v.excluded).collect(),
+                ..facet
+            });
         }
+        session.storage = Some(SessionStorage {
+            dir: dir.clone(),
+            name: meta.name,
+            created_at: meta.created_at,
+            entries: BufWriter::new(OpenOptions::new().append(true).open(dir.join(ENTRIES_FILE))?),
+            next_seq: entries.len(),
+        });
+        session.entries = entries;
+        session.index_entries(0);
+        session.apply_retention();
+        sessions.insert(id.clone(), session);
 
-        update_unique_facet_values_for_logging_session(session_id);
+        return Ok(id);
     }
 
-    #[tauri::command]
-    pub async fn set_filtered_for_facet_value(
-        session_id: String,
-        property: String,
-        value: String,
-        filtered: bool,
-    ) {
-        info!("Setting filtered for facet value in structured logging session: {}", session_id);
-        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .get_mut(&session_id)
-        {
-            for facet in session.facets.iter_mut() {
-                if facet.property == property {
-                    for facet_value in facet.values.iter_mut() {
-                        if facet_value.value == value {
-                            facet_value.filtered = filtered;
-                            break;
-                        }
-                    }
+    /// Reads the entries of a saved session. A last line that doesn't parse
+    /// was cut short by a crash while appending; it's logged and cut off the
+    /// file, so entries appended later start on a line of their own.
+    fn read_saved_entries(
+        path: &Path,
+    ) -> Result<VecDeque<StructuredLogEntry>, StructuredLoggingError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut entries = VecDeque::new();
+        let mut line = Vec::new();
+        let mut valid_len = 0;
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+            if line.iter().all(u8::is_ascii_whitespace) {
+                valid_len += read;
+                continue;
+            }
+            match serde_json::from_slice::<SavedEntry>(&line) {
+                Ok(saved) => {
+                    entries.push_back(StructuredLogEntry::new(
+                        saved.content,
+                        saved.timestamp,
+                        saved.data,
+                    ));
+                }
+                Err(e) if reader.fill_buf()?.is_empty() => {
+                    warn!("Dropping incomplete last entry of {}: {}", path.display(), e);
+                    OpenOptions::new().write(true).open(path)?.set_len(valid_len as u64)?;
                     break;
                 }
+                Err(e) => return Err(e.into()),
+            }
+            valid_len += read;
+            if !line.ends_with(b"\n") {
+                // Complete, but the newline after it was never written
+                OpenOptions::new().append(true).open(path)?.write_all(b"\n")?;
             }
         }
+
+        return Ok(entries);
     }
 
     #[tauri::command]
-    pub async fn get_facets_for_structured_logging_session(session_id: String) -> Vec<Facet> {
-        info!("Getting facets for structured logging session: {}", session_id);
+    pub async fn rename_saved_structured_logging_session(
+        app_handle: tauri::AppHandle,
+        id: String,
+        name: String,
+    ) -> Result<(), StructuredLoggingError> {
+        info!("Renaming saved structured logging session: {}", id);
+        return rename_saved_session(&saved_sessions_dir(&app_handle)?, id, name);
+    }
+
+    fn rename_saved_session(
+        sessions_dir: &Path,
+        id: String,
+        name: String,
+    ) -> Result<(), StructuredLoggingError> {
         if let Some(session) = STRUCTURED_LOGGING_SESSIONS
             .lock()
             .unwrap()
             .as_mut()
-            .unwrap()
-            .get_mut(&session_id)
+            .and_then(|sessions| sessions.get_mut(&id))
         {
-            return session.facets.clone();
+            if let Some(storage) = &mut session.storage {
+                storage.name = name;
+                session.save_meta();
+                return Ok(());
+            }
         }
 
-        return Vec::new();
+        let dir = saved_session_dir(sessions_dir, &id)?;
+        let mut meta: SessionMeta = serde_json::from_slice(&fs::read(dir.join(META_FILE))?)?;
+        meta.name = name;
+        write_meta(&dir, &meta)?;
+
+        return Ok(());
     }
 
+    /// Deletes a saved session from disk. If it is open, it stays open but is
+    /// no longer saved.
     #[tauri::command]
-    pub async fn get_columns_for_structured_logging_session(session_id: String) -> Vec<String> {
-        info!("Getting columns for structured logging session: {}", session_id);
+    pub async fn delete_saved_structured_logging_session(
+        app_handle: tauri::AppHandle,
+        id: String,
+    ) -> Result<(), StructuredLoggingError> {
+        info!("Deleting saved structured logging session: {}", id);
+        return delete_saved_session(&saved_sessions_dir(&app_handle)?, id);
+    }
+
+    fn delete_saved_session(sessions_dir: &Path, id: String) -> Result<(), StructuredLoggingError> {
         if let Some(session) = STRUCTURED_LOGGING_SESSIONS
             .lock()
             .unwrap()
             .as_mut()
-            .unwrap()
-            .get_mut(&session_id)
+            .and_then(|sessions| sessions.get_mut(&id))
         {
-            return session.columns.clone();
+            session.storage = None;
         }
 
-        return Vec::new();
+        let dir = saved_session_dir(sessions_dir, &id)?;
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+
+        return Ok(());
     }
 
-    #[tauri::command]
-    pub async fn get_filtered_data_for_structured_logging_session(
-        session_id: String,
+    /// A named preset of facets, query, sorting and visible columns. Views
+    /// with a `scope` (e.g. a workload) are only listed for that scope, views
+    /// without one everywhere.
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+    pub struct LogView {
+        #[serde(default)]
+        id: String,
+        name: String,
+        scope: Option<String>,
+        facets: Vec<LogViewFacet>,
         search_query: String,
-        mut sorting: Vec<SortingState>,
-    ) -> FilteredLogResult {
-        info!("Getting filtered data for structured logging session: {}", session_id);
-        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .get_mut(&session_id)
-        {
-            let mut filtered_data = Vec::new();
-            let mut any_filtered = false;
-            let mut facet_matches: Vec<HashMap<String, HashSet<usize>>> = Vec::new();
+        sorting: Vec<SortingState>,
+        visible_columns: Vec<String>,
+    }
 
-            // Collect all matching rows for each filtered facet value
-            for facet in session.facets.iter() {
-                let mut facet_matched_indices: HashSet<usize> = HashSet::new();
-
-                for facet_value in facet.values.iter() {
-                    if facet_value.filtered {
-                        any_filtered = true;
-                        for (index, entry) in session.entries.iter().enumerate() {
-                            if let Some(value) = entry.data.get(&facet.property) {
-                                let key = serde_json::to_string(value).unwrap();
-                                if key.as_str() == &facet_value.value {
-                                    facet_matched_indices.insert(index);
-                                }
-                            }
-                        }
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+    pub struct LogViewFacet {
+        property: String,
+        match_type: MatchType,
+        /// Facet value keys that are filtered on.
+        selected: Vec<String>,
+        /// Facet value keys that are excluded.
+        #[serde(default)]
+        excluded: Vec<String>,
+    }
+
+    /// Serializes read-modify-write cycles of the views file.
+    static LOG_VIEWS_LOCK: Mutex<()> = Mutex::new(());
+
+    fn log_views_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, StructuredLoggingError> {
+        Ok(app_handle.path().app_data_dir()?.join("log_views.json"))
+    }
+
+    fn read_log_views(path: &Path) -> Result<Vec<LogView>, StructuredLoggingError> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    fn write_log_views(path: &Path, views: &[LogView]) -> Result<(), StructuredLoggingError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(views)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    impl LogViewFacet {
+        /// Drops repeated values and resolves values that are both selected
+        /// and excluded in favour of the latest action: whichever list the
+        /// value wasn't in already in `previous`, excluded if there's no telling.
+        fn normalize(&mut self, previous: Option<&LogViewFacet>) {
+            let mut seen = HashSet::new();
+            self.selected.retain(|value| seen.insert(value.clone()));
+            seen.clear();
+            self.excluded.retain(|value| seen.insert(value.clone()));
+
+            let was_excluded =
+                |value: &String| previous.is_some_and(|p| p.excluded.contains(value));
+            let both: Vec<String> =
+                self.selected.iter().filter(|v| self.excluded.contains(v)).cloned().collect();
+            for value in both {
+                if was_excluded(&value) {
+                    self.excluded.retain(|v| *v != value);
+                } else {
+                    self.selected.retain(|v| *v != value);
+                }
+            }
+        }
+    }
+
+    impl LogView {
+        /// Keeps the last facet per property and normalizes its values against
+        /// the same facet of `previous`, the stored version of the view.
+        fn normalize(&mut self, previous: Option<&LogView>) {
+            let mut seen = HashSet::new();
+            let mut facets: Vec<LogViewFacet> = self
+                .facets
+                .drain(..)
+                .rev()
+                .filter(|facet| seen.insert(facet.property.clone()))
+                .collect();
+            facets.reverse();
+            for facet in facets.iter_mut() {
+                let previous_facet = previous
+                    .and_then(|view| view.facets.iter().find(|f| f.property == facet.property));
+                facet.normalize(previous_facet);
+            }
+            self.facets = facets;
+        }
+    }
+
+    /// Lists the global views and, if a scope is given, the views of that scope.
+    #[tauri::command]
+    pub async fn list_log_views(
+        app_handle: tauri::AppHandle,
+        scope: Option<String>,
+    ) -> Result<Vec<LogView>, StructuredLoggingError> {
+        info!("Listing log views for scope: {:?}", scope);
+        return list_views(&log_views_path(&app_handle)?, scope);
+    }
+
+    fn list_views(
+        path: &Path,
+        scope: Option<String>,
+    ) -> Result<Vec<LogView>, StructuredLoggingError> {
+        let _lock = LOG_VIEWS_LOCK.lock().unwrap();
+        let views = read_log_views(path)?;
+        return Ok(views
+            .into_iter()
+            .filter(|view| view.scope.is_none() || view.scope == scope)
+            .collect());
+    }
+
+    /// Creates a view, or replaces the one with the same id.
+    #[tauri::command]
+    pub async fn save_log_view(
+        app_handle: tauri::AppHandle,
+        view: LogView,
+    ) -> Result<LogView, StructuredLoggingError> {
+        info!("Saving log view: {}", view.name);
+        return save_view(&log_views_path(&app_handle)?, view);
+    }
+
+    fn save_view(path: &Path, mut view: LogView) -> Result<LogView, StructuredLoggingError> {
+        let _lock = LOG_VIEWS_LOCK.lock().unwrap();
+        let mut views = read_log_views(path)?;
+        if view.id.is_empty() {
+            view.id = Uuid::new_v4().to_string();
+        }
+        match views.iter_mut().find(|v| v.id == view.id) {
+            Some(existing) => {
+                view.normalize(Some(existing));
+                *existing = view.clone();
+            }
+            None => {
+                view.normalize(None);
+                views.push(view.clone());
+            }
+        }
+        write_log_views(path, &views)?;
+
+        return Ok(view);
+    }
+
+    #[tauri::command]
+    pub async fn delete_log_view(
+        app_handle: tauri::AppHandle,
+        id: String,
+    ) -> Result<(), StructuredLoggingError> {
+        info!("Deleting log view: {}", id);
+        return delete_view(&log_views_path(&app_handle)?, id);
+    }
+
+    fn delete_view(path: &Path, id: String) -> Result<(), StructuredLoggingError> {
+        let _lock = LOG_VIEWS_LOCK.lock().unwrap();
+        let mut views = read_log_views(path)?;
+        views.retain(|v| v.id != id);
+        write_log_views(path, &views)?;
+
+        return Ok(());
+    }
+
+    /// Replaces the facets, query and sorting of a session with those of a
+    /// view. The view is returned so the caller can apply its visible columns.
+    #[tauri::command]
+    pub async fn apply_log_view_to_structured_logging_session(
+        app_handle: tauri::AppHandle,
+        session_id: String,
+        view_id: String,
+    ) -> Result<LogView, StructuredLoggingError> {
+        info!("Applying log view {} to structured logging session: {}", view_id, session_id);
+        return apply_view(&log_views_path(&app_handle)?, session_id, view_id);
+    }
+
+    fn apply_view(
+        path: &Path,
+        session_id: String,
+        view_id: String,
+    ) -> Result<LogView, StructuredLoggingError> {
+        let mut view = {
+            let _lock = LOG_VIEWS_LOCK.lock().unwrap();
+            read_log_views(path)?
+                .into_iter()
+                .find(|v| v.id == view_id)
+                .ok_or_else(|| StructuredLoggingError {
+                    message: format!("Log view not found: {}", view_id),
+                })?
+        };
+        // Views written before normalization on save may still carry duplicates
+        view.normalize(None);
+        query::parse(&view.search_query)?;
+
+        let mut sessions = STRUCTURED_LOGGING_SESSIONS.lock().unwrap();
+        let Some(session) = sessions.as_mut().unwrap().get_mut(&session_id) else {
+            return Err(StructuredLoggingError::session_not_found(&session_id));
+        };
+
+        session.facets.clear();
+        session.facet_index.clear();
+        for view_facet in view.facets.iter() {
+            let mut facet = Facet {
+                property: view_facet.property.clone(),
+                match_type: view_facet.match_type.clone(),
+                values: view_facet
+                    .selected
+                    .iter()
+                    .map(|value| (value, true))
+                    .chain(view_facet.excluded.iter().map(|value| (value, false)))
+                    .map(|(value, filtered)| FacetValue {
+                        value: value.clone(),
+                        filtered,
+                        excluded: !filtered,
+                        total: 0,
+                    })
+                    .collect(),
+            };
+            let mut index = HashMap::new();
+            index_facet(&mut facet, &mut index, &session.entries, session.first_seq, 0);
+            session.facet_index.insert(facet.property.clone(), index);
+            session.facets.push(facet);
+        }
+        session.view_state = ViewState {
+            search_query: view.search_query.clone(),
+            sorting: view.sorting.clone(),
+        };
+        session.invalidate_view();
+        session.save_meta();
+
+        return Ok(view);
+    }
+
+    #[derive(Debug)]
+    enum ExtractedContent {
+        Json(Value),
+        Text(String),
+    }
+
+    fn extract_content(input: &str) -> Vec<ExtractedContent> {
+        let mut extracted_content = Vec::new();
+        let mut start_index = None;
+        let mut brace_count = 0;
+        let mut last_end_index = 0;
+
+        for (i, c) in input.char_indices() {
+            match c {
+                '{' => {
+                    if brace_count == 0 && i > last_end_index {
+                        // Capture text before JSON
+                        extracted_content
+                            .push(ExtractedContent::Text(input[last_end_index..i].to_string()));
+                    }
+                    if start_index.is_none() {
+                        start_index = Some(i);
+                    }
+                    brace_count += 1;
+                }
+                '}' => {
+                    if brace_count > 0 {
+                        brace_count -= 1;
+                    }
+                    if brace_count == 0 {
+                        if let Some(start) = start_index {
+                            let json_str = &input[start..=i];
+                            if let Ok(value) = serde_json::from_str::<Value>(json_str) {
+                                extracted_content.push(ExtractedContent::Json(value));
+                            }
+                            last_end_index = i + 1;
+                            start_index = None; // Reset for the next potential JSON object
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Capture any remaining text after the last JSON object
+        if last_end_index < input.len() {
+            extracted_content.push(ExtractedContent::Text(input[last_end_index..].to_string()));
+        }
+
+        extracted_content
+    }
+
+    fn parse_line(line: &str) -> Vec<StructuredLogEntry> {
+        let (timestamp, data) = split_timestamp(line);
+        extract_content(data)
+            .into_iter()
+            .map(|content| match content {
+                ExtractedContent::Json(json) => {
+                    StructuredLogEntry::new(data.to_string(), timestamp.to_string(), json)
+                }
+                ExtractedContent::Text(text) => StructuredLogEntry::new(
+                    data.to_string(),
+                    timestamp.to_string(),
+                    parse_log_record(&text),
+                ),
+            })
+            .collect()
+    }
+
+    /// Splits off the `kubectl logs --timestamps` prefix, if any. Without one
+    /// the timestamp is empty and the whole line is the body.
+    fn split_timestamp(line: &str) -> (&str, &str) {
+        match line.split_once(' ') {
+            Some((timestamp, body)) if parse_time(timestamp).is_some() => (timestamp, body),
+            _ => ("", line),
+        }
+    }
+
+    fn line_body(line: &str) -> &str {
+        split_timestamp(line).1
+    }
+
+    /// An incomplete line longer than this is taken as complete rather than
+    /// buffered any further.
+    const MAX_PENDING_BYTES: usize = 1024 * 1024;
+
+    /// Adds a chunk of raw log output. Text after the last newline is held
+    /// back until the next chunk completes it, or until the session is flushed.
+    #[tauri::command]
+    pub async fn add_data_to_structured_logging_session(
+        app_handle: tauri::AppHandle,
+        session_id: String,
+        data: String,
+    ) {
+        info!("Adding data to structured logging session: {}", session_id);
+        let alerts = add_data(&session_id, data);
+        notify_alerts(&app_handle, &session_id, alerts);
+    }
+
+    fn add_data(session_id: &str, data: String) -> Vec<WatchAlert> {
+        let Some((multiline, data)) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(session_id)
+            .map(|session| {
+                let mut data = std::mem::take(&mut session.pending) + &data;
+                match data.rfind('\n') {
+                    Some(end) => {
+                        session.pending = data.split_off(end + 1);
+                        data.truncate(end);
+                    }
+                    None if data.len() <= MAX_PENDING_BYTES => {
+                        session.pending = std::mem::take(&mut data);
                     }
+                    None => {}
+                }
+                (session.multiline.clone(), data)
+            })
+        else {
+            return Vec::new();
+        };
+
+        ingest_lines(session_id, &multiline, &data, true)
+    }
+
+    /// Treats any buffered incomplete line as complete, e.g. when the log
+    /// stream ends.
+    #[tauri::command]
+    pub async fn flush_structured_logging_session(
+        app_handle: tauri::AppHandle,
+        session_id: String,
+    ) {
+        info!("Flushing structured logging session: {}", session_id);
+        let alerts = flush_pending(&session_id);
+        notify_alerts(&app_handle, &session_id, alerts);
+
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            session.save_entries(true);
+        }
+    }
+
+    /// Ingests the buffered incomplete line, if any.
+    fn flush_pending(session_id: &str) -> Vec<WatchAlert> {
+        let Some((multiline, data)) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(session_id)
+            .map(|session| (session.multiline.clone(), std::mem::take(&mut session.pending)))
+        else {
+            return Vec::new();
+        };
+
+        ingest_lines(session_id, &multiline, &data, true)
+    }
+
+    /// Shows a desktop notification and emits a `structured_logging_alert_{id}`
+    /// event per alert.
+    fn notify_alerts(app_handle: &tauri::AppHandle, session_id: &str, alerts: Vec<WatchAlert>) {
+        for alert in alerts {
+            info!("Watch rule {} fired for session {}", alert.rule.name, session_id);
+            let body = format!(
+                "{} matches of {} in {}s: {}",
+                alert.count,
+                alert.rule.query,
+                alert.rule.window_seconds,
+                alert.entry.message(),
+            );
+            if let Err(e) = app_handle
+                .notification()
+                .builder()
+                .title(&alert.rule.name)
+                .body(body)
+                .show()
+            {
+                warn!("Failed to show notification for watch rule: {:?}", e);
+            }
+            let _ = app_handle.emit(&format!("structured_logging_alert_{}", session_id), alert);
+        }
+    }
+
+    /// Parses and adds complete lines to a session. Watch rules are only
+    /// checked for live data (`watch`), not for imported files.
+    fn ingest_lines(
+        session_id: &str,
+        multiline: &MultilineRules,
+        data: &str,
+        watch: bool,
+    ) -> Vec<WatchAlert> {
+        if data.is_empty() {
+            return Vec::new();
+        }
+
+        // split the data by newline if there's any, merging continuation lines
+        // into the entry before them
+        let mut parsed_records: Vec<StructuredLogEntry> = Vec::new();
+        let mut leading_continuations: Vec<&str> = Vec::new();
+        for line in data.split("\n") {
+            let body = line_body(line);
+            if multiline.is_continuation(body) {
+                match parsed_records.last_mut() {
+                    Some(entry) => entry.append_line(body),
+                    None => leading_continuations.push(line),
+                }
+                continue;
+            }
+            parsed_records.extend(parse_line(line));
+        }
+
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(session_id)
+        {
+            // Continuations at the start of a batch belong to the last entry
+            // of the previous one, if there is one
+            if !leading_continuations.is_empty() {
+                if !session.entries.is_empty() {
+                    let bodies = leading_continuations
+                        .into_iter()
+                        .map(line_body)
+                        .collect::<Vec<&str>>();
+                    session.append_to_last_entry(&bodies);
+                } else {
+                    let mut records = leading_continuations
+                        .into_iter()
+                        .flat_map(parse_line)
+                        .collect::<Vec<StructuredLogEntry>>();
+                    records.append(&mut parsed_records);
+                    parsed_records = records;
+                }
+            }
+
+            let start = session.entries.len();
+            session.entries.extend(parsed_records);
+            session.index_entries(start);
+            let alerts = if watch { session.check_watches(start) } else { Vec::new() };
+            // The last entry may still receive continuation lines
+            session.save_entries(false);
+            session.apply_retention();
+            return alerts;
+        }
+
+        Vec::new()
+    }
+
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct ImportProgress {
+        /// Bytes read from the file, compressed if it is gzipped.
+        bytes_read: u64,
+        total_bytes: u64,
+        lines: u64,
+        done: bool,
+        error: Option<String>,
+    }
+
+    /// Lines are handed to the session this many at a time, with a progress
+    /// event after each batch.
+    const IMPORT_BATCH_LINES: usize = 5000;
+
+    struct CountingReader<R> {
+        inner: R,
+        count: Arc<AtomicU64>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let read = self.inner.read(buf)?;
+            self.count.fetch_add(read as u64, Ordering::Relaxed);
+            Ok(read)
+        }
+    }
+
+    /// Streams a local log file, plain or gzipped, into a session in the
+    /// background. Progress is reported through
+    /// `structured_logging_import_{session_id}` events.
+    #[tauri::command]
+    pub async fn import_file_to_structured_logging_session(
+        app_handle: tauri::AppHandle,
+        session_id: String,
+        path: String,
+    ) -> Result<(), StructuredLoggingError> {
+        info!("Importing {} into structured logging session: {}", path, session_id);
+        if !STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|s| s.contains_key(&session_id))
+        {
+            return Err(StructuredLoggingError::session_not_found(&session_id));
+        }
+
+        let total_bytes = fs::metadata(&path)?.len();
+        let count = Arc::new(AtomicU64::new(0));
+        let reader = open_log_file(&path, count.clone())?;
+
+        thread::spawn(move || {
+            let event = format!("structured_logging_import_{}", session_id);
+            let mut progress = ImportProgress {
+                bytes_read: 0,
+                total_bytes,
+                lines: 0,
+                done: false,
+                error: None,
+            };
+            let result = import_lines(reader, &session_id, |lines| {
+                progress.lines = lines;
+                progress.bytes_read = count.load(Ordering::Relaxed);
+                let _ = app_handle.emit(&event, progress.clone());
+            });
+
+            progress.bytes_read = count.load(Ordering::Relaxed);
+            progress.done = true;
+            match result {
+                Ok(lines) => progress.lines = lines,
+                Err(e) => {
+                    error!("Error importing {}: {:?}", path, e);
+                    progress.error = Some(e.to_string());
+                }
+            }
+            let _ = app_handle.emit(&event, progress);
+        });
+
+        return Ok(());
+    }
+
+    /// Opens a plain or gzipped file for reading, adding the number of bytes
+    /// read from disk to `count` as it goes.
+    fn open_log_file(
+        path: &str,
+        count: Arc<AtomicU64>,
+    ) -> std::io::Result<Box<dyn BufRead + Send>> {
+        let mut file = File::open(path)?;
+        let mut magic = [0u8; 2];
+        let is_gzip = file.read_exact(&mut magic).is_ok() && magic == [0x1f, 0x8b];
+        drop(file);
+
+        let reader = CountingReader {
+            inner: File::open(path)?,
+            count,
+        };
+        if is_gzip {
+            return Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))));
+        }
+        Ok(Box::new(BufReader::new(reader)))
+    }
+
+    /// Feeds lines to the session in batches until the reader is exhausted or
+    /// the session ends, returning the number of lines read.
+    fn import_lines(
+        mut reader: impl BufRead,
+        session_id: &str,
+        mut on_batch: impl FnMut(u64),
+    ) -> std::io::Result<u64> {
+        let mut lines = 0;
+        let mut line = Vec::new();
+        let mut batch = String::new();
+        let mut batch_lines = 0;
+        loop {
+            line.clear();
+            let eof = reader.read_until(b'\n', &mut line)? == 0;
+            if !eof {
+                batch.push_str(&String::from_utf8_lossy(&line));
+                batch_lines += 1;
+                lines += 1;
+            }
+
+            if eof || batch_lines == IMPORT_BATCH_LINES {
+                let Some(multiline) = STRUCTURED_LOGGING_SESSIONS
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .unwrap()
+                    .get(session_id)
+                    .map(|session| session.multiline.clone())
+                else {
+                    return Ok(lines);
+                };
+                ingest_lines(session_id, &multiline, batch.trim_end_matches('\n'), false);
+                on_batch(lines);
+                batch.clear();
+                batch_lines = 0;
+            }
+
+            if eof {
+                return Ok(lines);
+            }
+        }
+    }
+
+    #[tauri::command]
+    pub async fn set_multiline_config_for_structured_logging_session(
+        session_id: String,
+        config: MultilineConfig,
+    ) -> Result<(), StructuredLoggingError> {
+        info!("Setting multiline config for structured logging session: {}", session_id);
+        let rules = MultilineRules::new(config)?;
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            session.multiline = rules;
+            session.save_meta();
+        }
+
+        return Ok(());
+    }
+
+    #[tauri::command]
+    pub async fn get_multiline_config_for_structured_logging_session(
+        session_id: String,
+    ) -> MultilineConfig {
+        info!("Getting multiline config for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            return session.multiline.config.clone();
+        }
+
+        return MultilineConfig::default();
+    }
+
+    /// Replaces the session's watch rules, resetting their state. Fails
+    /// without changing anything if a rule's query is invalid.
+    #[tauri::command]
+    pub async fn set_watch_rules_for_structured_logging_session(
+        session_id: String,
+        rules: Vec<WatchRule>,
+    ) -> Result<(), StructuredLoggingError> {
+        info!("Setting watch rules for structured logging session: {}", session_id);
+        let watches = rules
+            .into_iter()
+            .map(Watch::new)
+            .collect::<Result<Vec<Watch>, QueryError>>()?;
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            session.watches = watches;
+            session.save_meta();
+        }
+
+        return Ok(());
+    }
+
+    #[tauri::command]
+    pub async fn get_watch_rules_for_structured_logging_session(
+        session_id: String,
+    ) -> Vec<WatchRule> {
+        info!("Getting watch rules for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            return session.watches.iter().map(|watch| watch.rule.clone()).collect();
+        }
+
+        return Vec::new();
+    }
+
+    const ACCESS_LOG_TYPES: [LogType; 6] = [
+        LogType::CommonLog,
+        LogType::CombinedLog,
+        LogType::GorouterLog,
+        LogType::CloudControllerLog,
+        LogType::Rfc5424Log,
+        LogType::Rfc3164Log,
+    ];
+
+    fn parse_log_record(data: &str) -> serde_json::Value {
+        match serde_json::from_str(data) {
+            Ok(json) => {
+                return json;
+            }
+            Err(_) => {}
+        }
+
+        // Most plain text lines match none of the formats, so skip the error reports
+        let record = ACCESS_LOG_TYPES
+            .iter()
+            .find_map(|log_type| access_log_parser::try_parse(*log_type, data));
+        if let Some(json) = record.and_then(|r| r.to_json_value().ok()) {
+            return json;
+        }
+
+        return json!({ "message": data });
+    }
+
+    /// Parses a single line with an explicitly chosen format, returning the
+    /// structured parse error (position, field, expected token) on mismatch.
+    #[tauri::command]
+    pub async fn parse_log_line(log_type: String, line: String) -> Result<Value, AccessLogError> {
+        info!("Parsing log line as {}", log_type);
+        let log_type = LogType::from_str(&log_type)
+            .map_err(|_| AccessLogError::InvalidLogType { log_type })?;
+        let record = access_log_parser::parse(log_type, &line)?;
+        Ok(record.to_json_value().unwrap_or(Value::Null))
+    }
+
+    #[tauri::command]
+    pub async fn add_facet_to_structured_logging_session(
+        session_id: String,
+        property: String,
+        match_type: String,
+    ) {
+        info!("Adding facet to structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            let match_type = match match_type.as_str() {
+                "AND" => MatchType::AND,
+                "OR" => MatchType::OR,
+                _ => MatchType::OR,
+            };
+            let mut facet = Facet {
+                property: property.clone(),
+                match_type: match_type,
+                values: Vec::new(),
+            };
+            let mut index = HashMap::new();
+            index_facet(&mut facet, &mut index, &session.entries, session.first_seq, 0);
+            session.facets.push(facet);
+            session.facet_index.insert(property, index);
+            session.invalidate_view();
+            session.save_meta();
+        }
+    }
+
+    #[tauri::command]
+    pub async fn set_facet_match_type_for_structured_logging_session(
+        session_id: String,
+        property: String,
+        match_type: String,
+    ) {
+        info!("Setting facet match type for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            for facet in session.facets.iter_mut() {
+                if facet.property == property {
+                    facet.match_type = match match_type.as_str() {
+                        "AND" => MatchType::AND,
+                        "OR" => MatchType::OR,
+                        _ => MatchType::OR,
+                    };
+                    break;
+                }
+            }
+            session.invalidate_view();
+            session.save_meta();
+        }
+    }
+
+    #[tauri::command]
+    pub async fn remove_facet_from_structured_logging_session(
+        session_id: String,
+        property: String,
+    ) {
+        info!("Removing facet from structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            session.facets.retain(|f| f.property != property);
+            session.facet_index.remove(&property);
+            session.invalidate_view();
+            session.save_meta();
+        }
+    }
+
+    #[tauri::command]
+    pub async fn set_filtered_for_facet_value(
+        session_id: String,
+        property: String,
+        value: String,
+        filtered: bool,
+    ) {
+        info!("Setting filtered for facet value in structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            for facet in session.facets.iter_mut() {
+                if facet.property == property {
+                    for facet_value in facet.values.iter_mut() {
+                        if facet_value.value == value {
+                            facet_value.filtered = filtered;
+                            facet_value.excluded &= !filtered;
+                            break;
+                        }
+                    }
+                    break;
+                }
+            }
+            session.invalidate_view();
+            session.save_meta();
+        }
+    }
+
+    /// Hides the entries with the given facet value, e.g. everything except
+    /// `/healthz`. Excluding a value deselects it.
+    #[tauri::command]
+    pub async fn set_excluded_for_facet_value(
+        session_id: String,
+        property: String,
+        value: String,
+        excluded: bool,
+    ) {
+        info!("Setting excluded for facet value in structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            for facet in session.facets.iter_mut() {
+                if facet.property == property {
+                    for facet_value in facet.values.iter_mut() {
+                        if facet_value.value == value {
+                            facet_value.excluded = excluded;
+                            facet_value.filtered &= !excluded;
+                            break;
+                        }
+                    }
+                    break;
+                }
+            }
+            session.invalidate_view();
+            session.save_meta();
+        }
+    }
+
+    #[tauri::command]
+    pub async fn set_retention_policy_for_structured_logging_session(
+        session_id: String,
+        policy: RetentionPolicy,
+    ) {
+        info!("Setting retention policy for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            session.retention = policy;
+            session.apply_retention();
+            session.save_meta();
+        }
+    }
+
+    #[tauri::command]
+    pub async fn get_eviction_stats_for_structured_logging_session(
+        session_id: String,
+    ) -> EvictionStats {
+        info!("Getting eviction stats for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            return session.eviction_stats.clone();
+        }
+
+        return EvictionStats::default();
+    }
+
+    /// The query and sorting the session was last viewed with, restored for
+    /// saved sessions and set by applied log views.
+    #[tauri::command]
+    pub async fn get_view_state_for_structured_logging_session(
+        session_id: String,
+    ) -> ViewState {
+        info!("Getting view state for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            return session.view_state.clone();
+        }
+
+        return ViewState::default();
+    }
+
+    #[tauri::command]
+    pub async fn get_facets_for_structured_logging_session(session_id: String) -> Vec<Facet> {
+        info!("Getting facets for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            return session.facets.clone();
+        }
+
+        return Vec::new();
+    }
+
+    #[tauri::command]
+    pub async fn get_columns_for_structured_logging_session(session_id: String) -> Vec<String> {
+        info!("Getting columns for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            let mut columns = BUILTIN_COLUMNS.map(String::from).to_vec();
+            columns.extend(
+                session
+                    .columns
+                    .iter()
+                    .filter(|c| !BUILTIN_COLUMNS.contains(&c.as_str()))
+                    .cloned(),
+            );
+            return columns;
+        }
+
+        return Vec::new();
+    }
+
+    /// Derived fields every entry has, listed first so they can be faceted.
+    const BUILTIN_COLUMNS: [&str; 2] = ["level", "pattern_id"];
+
+    /// Message templates of the retained entries with their counts, most
+    /// frequent first. `id` is the value of the entries' `pattern_id` field.
+    #[tauri::command]
+    pub async fn get_patterns_for_structured_logging_session(session_id: String) -> Vec<Cluster> {
+        info!("Getting patterns for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            return session.patterns.clusters();
+        }
+
+        return Vec::new();
+    }
+
+    /// Like `get_columns_for_structured_logging_session`, with the number of
+    /// entries carrying each column and the types of its values.
+    #[tauri::command]
+    pub async fn get_column_info_for_structured_logging_session(
+        session_id: String,
+    ) -> Vec<ColumnInfo> {
+        info!("Getting column info for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            return session
+                .columns
+                .iter()
+                .map(|path| {
+                    let counts = &session.column_types[path];
+                    let mut types = counts.keys().copied().collect::<Vec<ColumnType>>();
+                    types.sort();
+                    ColumnInfo {
+                        path: path.clone(),
+                        count: counts.values().sum(),
+                        types,
+                    }
+                })
+                .collect();
+        }
+
+        return Vec::new();
+    }
+
+    /// Returns a window of the filtered and sorted entries. `total` is the size
+    /// of the session, `filtered` the number of entries matching the facets,
+    /// query and time range. Without a `limit`, everything from `offset`
+    /// onwards is returned. `from` and `to` are inclusive epoch milliseconds.
+    #[tauri::command]
+    pub async fn get_filtered_data_for_structured_logging_session(
+        session_id: String,
+        search_query: String,
+        sorting: Vec<SortingState>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<FilteredLogResult, QueryError> {
+        info!("Getting filtered data for structured logging session: {}", session_id);
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            session.update_view(search_query, sorting, from, to)?;
+            let view = session.view_cache.as_ref().unwrap();
+            let indices = &view.indices;
+            let offset = offset.unwrap_or(0).min(indices.len());
+            let end = limit.map_or(indices.len(), |l| (offset + l).min(indices.len()));
+
+            return Ok(FilteredLogResult {
+                entries: indices[offset..end]
+                    .iter()
+                    .map(|index| session.entries[*index].clone())
+                    .collect(),
+                matches: view.query.as_ref().map_or_else(Vec::new, |query| {
+                    indices[offset..end]
+                        .iter()
+                        .map(|index| query.highlights(&session.entries[*index]))
+                        .collect()
+                }),
+                total: session.entries.len() as u32,
+                filtered: indices.len() as u32,
+                offset: offset as u32,
+                evicted: session.eviction_stats.evicted_entries,
+            });
+        }
+
+        return Ok(FilteredLogResult {
+            entries: Vec::new(),
+            matches: Vec::new(),
+            total: 0,
+            filtered: 0,
+            offset: 0,
+            evicted: 0,
+        });
+    }
+
+    #[derive(Clone, Copy, Debug, serde::Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ExportFormat {
+        Ndjson,
+        Csv,
+        Text,
+    }
+
+    #[derive(Clone, Debug, serde::Deserialize)]
+    pub struct ExportOptions {
+        path: String,
+        format: ExportFormat,
+        /// CSV columns; defaults to the timestamp, level and every discovered
+        /// column.
+        columns: Option<Vec<String>>,
+    }
+
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct ExportResult {
+        exported: u64,
+        /// Entries evicted from the session while the export was running.
+        skipped: u64,
+    }
+
+    /// Entries are copied out of the session this many at a time, so the lock
+    /// isn't held while writing and memory use stays flat.
+    const EXPORT_BATCH_SIZE: usize = 1000;
+
+    /// Writes the filtered and sorted view, as returned by
+    /// `get_filtered_data_for_structured_logging_session`, to a file.
+    #[tauri::command]
+    pub async fn export_structured_logging_session(
+        session_id: String,
+        search_query: String,
+        sorting: Vec<SortingState>,
+        from: Option<i64>,
+        to: Option<i64>,
+        options: ExportOptions,
+    ) -> Result<ExportResult, StructuredLoggingError> {
+        info!("Exporting structured logging session {} to {}", session_id, options.path);
+        // Snapshot the view as sequence numbers, which stay valid while
+        // entries are evicted
+        let (seqs, columns) = {
+            let mut sessions = STRUCTURED_LOGGING_SESSIONS.lock().unwrap();
+            let Some(session) = sessions.as_mut().unwrap().get_mut(&session_id) else {
+                return Err(StructuredLoggingError::session_not_found(&session_id));
+            };
+            session.update_view(search_query, sorting, from, to)?;
+            let seqs = session
+                .view_cache
+                .as_ref()
+                .unwrap()
+                .indices
+                .iter()
+                .map(|position| session.first_seq + position)
+                .collect::<Vec<usize>>();
+            let columns = options.columns.clone().unwrap_or_else(|| {
+                let mut columns = vec!["timestamp".to_string(), "level".to_string()];
+                for column in session.columns.iter() {
+                    if !columns.contains(column) {
+                        columns.push(column.clone());
+                    }
+                }
+                columns
+            });
+            (seqs, columns)
+        };
+
+        let mut writer = BufWriter::new(File::create(&options.path)?);
+        if let ExportFormat::Csv = options.format {
+            write_csv_row(&mut writer, columns.iter().map(|c| c.as_str()))?;
+        }
+
+        let mut exported = 0;
+        for batch in seqs.chunks(EXPORT_BATCH_SIZE) {
+            let entries = match STRUCTURED_LOGGING_SESSIONS
+                .lock()
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .get(&session_id)
+            {
+                Some(session) => batch
+                    .iter()
+                    .filter_map(|seq| session.entries.get(seq.checked_sub(session.first_seq)?))
+                    .cloned()
+                    .collect::<Vec<StructuredLogEntry>>(),
+                None => break,
+            };
+
+            for entry in entries.iter() {
+                match options.format {
+                    ExportFormat::Ndjson => {
+                        serde_json::to_writer(&mut writer, entry).map_err(std::io::Error::from)?;
+                        writer.write_all(b"\n")?;
+                    }
+                    ExportFormat::Csv => {
+                        let values = columns
+                            .iter()
+                            .map(|column| export_field(entry, column))
+                            .collect::<Vec<String>>();
+                        write_csv_row(&mut writer, values.iter().map(|v| v.as_str()))?;
+                    }
+                    ExportFormat::Text => {
+                        if !entry.timestamp.is_empty() {
+                            write!(writer, "{} ", entry.timestamp)?;
+                        }
+                        writeln!(writer, "{}", entry.content)?;
+                    }
+                }
+            }
+            exported += entries.len() as u64;
+        }
+        writer.flush()?;
+
+        return Ok(ExportResult {
+            exported,
+            skipped: seqs.len() as u64 - exported,
+        });
+    }
+
+    /// The entry's own timestamp and content take precedence over fields of
+    /// the same name in its data.
+    fn export_field(entry: &StructuredLogEntry, column: &str) -> String {
+        match column {
+            "timestamp" => entry.timestamp.clone(),
+            "content" => entry.content.clone(),
+            _ => entry.get(column).map(|value| query::value_to_string(&value)).unwrap_or_default(),
+        }
+    }
+
+    fn write_csv_row<'a>(
+        writer: &mut impl Write,
+        fields: impl Iterator<Item = &'a str>,
+    ) -> std::io::Result<()> {
+        for (i, field) in fields.enumerate() {
+            if i > 0 {
+                writer.write_all(b",")?;
+            }
+            if field.contains([',', '"', '\n', '\r']) {
+                write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+            } else {
+                writer.write_all(field.as_bytes())?;
+            }
+        }
+        writer.write_all(b"\r\n")
+    }
+
+    impl StructuredLoggingSession {
+        /// Computes the positions of the entries in the filtered and sorted view
+        /// into `view_cache`, unless the cached view is still current.
+        fn update_view(
+            &mut self,
+            search_query: String,
+            mut sorting: Vec<SortingState>,
+            from: Option<i64>,
+            to: Option<i64>,
+        ) -> Result<(), QueryError> {
+            let view_state = ViewState {
+                search_query: search_query.clone(),
+                sorting: sorting.clone(),
+            };
+
+            // Apply sorting, default to timestamp
+            if sorting.is_empty() {
+                sorting.push(SortingState {
+                    id: "timestamp".to_string(),
+                    desc: false,
+                });
+            }
+
+            let cached = self.view_cache.as_ref().is_some_and(|c| {
+                c.search_query == search_query
+                    && c.sorting == sorting
+                    && c.from == from
+                    && c.to == to
+            });
+            if !cached {
+                let query = query::parse(&search_query)?;
+                let mut indices = get_filtered_indices(self, query.as_ref(), from, to, 0);
+                apply_sorting(&self.entries, &mut indices, &sorting);
+                self.view_cache = Some(ViewCache {
+                    search_query,
+                    query,
+                    sorting,
+                    from,
+                    to,
+                    indices,
+                    len: self.entries.len(),
+                });
+            }
+            // Only kept in memory here; written with the meta on the next save
+            self.view_state = view_state;
+
+            Ok(())
+        }
+    }
+
+    /// Positions of the entries from `start` onwards that pass the facet
+    /// selections, the time range and the query, in ascending order.
+    fn get_filtered_indices(
+        session: &StructuredLoggingSession,
+        query: Option<&query::Query>,
+        from: Option<i64>,
+        to: Option<i64>,
+        start: usize,
+    ) -> Vec<usize> {
+        let mut and_matches: Option<HashSet<usize>> = None;
+        let mut or_matches: Option<HashSet<usize>> = None;
+        let mut excluded: HashSet<usize> = HashSet::new();
+
+        for facet in session.facets.iter() {
+            let Some(index) = session.facet_index.get(&facet.property) else {
+                continue;
+            };
+            // Sequence numbers are ascending, so the wanted ones are at the end
+            let positions = |facet_value: &FacetValue| {
+                index
+                    .get(&facet_value.value)
+                    .into_iter()
+                    .flat_map(|seqs| seqs.iter().rev())
+                    .take_while(|seq| **seq >= session.first_seq + start)
+                    .map(|seq| seq - session.first_seq)
+            };
+            excluded.extend(facet.values.iter().filter(|v| v.excluded).flat_map(positions));
+
+            // A facet without selected values doesn't restrict anything
+            if !facet.values.iter().any(|v| v.filtered) {
+                continue;
+            }
+            let selected: HashSet<usize> =
+                facet.values.iter().filter(|v| v.filtered).flat_map(positions).collect();
+            match facet.match_type {
+                MatchType::AND => {
+                    and_matches = Some(match and_matches {
+                        Some(matches) => matches.intersection(&selected).cloned().collect(),
+                        None => selected,
+                    });
+                }
+                MatchType::OR => {
+                    or_matches.get_or_insert_with(HashSet::new).extend(selected);
+                }
+            }
+        }
+
+        // Without facet selections every entry is a candidate
+        let mut indices: Vec<usize> = match (and_matches, or_matches) {
+            (None, None) => (start..session.entries.len()).collect(),
+            (Some(matches), None) | (None, Some(matches)) => matches.into_iter().collect(),
+            (Some(and_matches), Some(or_matches)) => {
+                and_matches.intersection(&or_matches).cloned().collect()
+            }
+        };
+        if !excluded.is_empty() {
+            indices.retain(|index| !excluded.contains(index));
+        }
+        indices.sort_unstable();
+
+        // Entries without a known time never fall inside a time range
+        if from.is_some() || to.is_some() {
+            indices.retain(|index| {
+                session.entries[*index].time.is_some_and(|time| {
+                    from.is_none_or(|from| time >= from) && to.is_none_or(|to| time <= to)
+                })
+            });
+        }
+
+        if let Some(query) = query {
+            indices.retain(|index| query.matches(&session.entries[*index]));
+        }
+
+        indices
+    }
+
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct Histogram {
+        bucket_size: i64,
+        buckets: Vec<HistogramBucket>,
+    }
+
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct HistogramBucket {
+        start: i64,
+        total: u32,
+        /// Counts per value of the `split_by` field; entries without it are
+        /// only counted in `total`.
+        counts: HashMap<String, u32>,
+    }
+
+    /// Bucket sizes in milliseconds the histogram picks from, smallest first.
+    const HISTOGRAM_BUCKET_SIZES: [i64; 14] = [
+        1_000,
+        5_000,
+        10_000,
+        30_000,
+        60_000,
+        300_000,
+        600_000,
+        1_800_000,
+        3_600_000,
+        10_800_000,
+        21_600_000,
+        43_200_000,
+        86_400_000,
+        604_800_000,
+    ];
+
+    const MAX_HISTOGRAM_BUCKETS: u32 = 1000;
+
+    /// Counts the filtered entries over time, in at most `max_buckets` (capped
+    /// at `MAX_HISTOGRAM_BUCKETS`) buckets of a round size, optionally split by
+    /// the values of a (dotted) field.
+    #[tauri::command]
+    pub async fn get_histogram_for_structured_logging_session(
+        session_id: String,
+        search_query: String,
+        from: Option<i64>,
+        to: Option<i64>,
+        split_by: Option<String>,
+        max_buckets: Option<u32>,
+    ) -> Result<Histogram, QueryError> {
+        info!("Getting histogram for structured logging session: {}", session_id);
+        let query = query::parse(&search_query)?;
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            let indices = get_filtered_indices(session, query.as_ref(), from, to, 0);
+            let times = indices
+                .iter()
+                .filter_map(|index| session.entries[*index].time.map(|time| (*index, time)))
+                .collect::<Vec<(usize, i64)>>();
+
+            let (Some(min), Some(max)) = (
+                from.or(times.iter().map(|(_, t)| *t).min()),
+                to.or(times.iter().map(|(_, t)| *t).max()),
+            ) else {
+                return Ok(Histogram {
+                    bucket_size: HISTOGRAM_BUCKET_SIZES[0],
+                    buckets: Vec::new(),
+                });
+            };
+            if max < min {
+                return Ok(Histogram {
+                    bucket_size: HISTOGRAM_BUCKET_SIZES[0],
+                    buckets: Vec::new(),
+                });
+            }
+
+            let max_buckets = max_buckets.unwrap_or(60).clamp(1, MAX_HISTOGRAM_BUCKETS) as i64;
+            let span = max.saturating_sub(min);
+            let fitting = HISTOGRAM_BUCKET_SIZES.iter().find(|size| span / **size < max_buckets);
+            let bucket_size = match fitting {
+                Some(size) => *size,
+                // Wider ranges get a multiple of the largest size
+                None => {
+                    let largest = HISTOGRAM_BUCKET_SIZES[HISTOGRAM_BUCKET_SIZES.len() - 1];
+                    (span / max_buckets / largest + 1).saturating_mul(largest)
+                }
+            };
+            let first = min.div_euclid(bucket_size).saturating_mul(bucket_size);
+            let count = (max.saturating_sub(first) / bucket_size + 1).min(max_buckets + 1) as usize;
+
+            let mut buckets = (0..count)
+                .map(|i| HistogramBucket {
+                    start: first + i as i64 * bucket_size,
+                    total: 0,
+                    counts: HashMap::new(),
+                })
+                .collect::<Vec<HistogramBucket>>();
+
+            for (index, time) in times {
+                let position = time.saturating_sub(first) / bucket_size;
+                let Some(bucket) = buckets.get_mut(position as usize) else {
+                    continue;
+                };
+                bucket.total += 1;
+                if let Some(value) = split_by
+                    .as_ref()
+                    .and_then(|field| session.entries[index].field(field))
+                {
+                    *bucket.counts.entry(query::value_to_string(&value)).or_insert(0) += 1;
+                }
+            }
+
+            return Ok(Histogram {
+                bucket_size,
+                buckets,
+            });
+        }
+
+        return Ok(Histogram {
+            bucket_size: HISTOGRAM_BUCKET_SIZES[0],
+            buckets: Vec::new(),
+        });
+    }
+
+    /// How often a template or facet value occurs in two sessions.
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct FrequencyChange {
+        value: String,
+        base_count: usize,
+        other_count: usize,
+        /// Fraction of the session's entries, from 0 to 1.
+        base_share: f64,
+        other_share: f64,
+        /// `other_share / base_share`; `None` if the value only occurs in one
+        /// of the sessions.
+        ratio: Option<f64>,
+    }
+
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct FacetComparison {
+        property: String,
+        values: Vec<FrequencyChange>,
+    }
+
+    /// Lists are ordered by significance: values only in one session first,
+    /// then by how much their frequency changed.
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct SessionComparison {
+        base_total: usize,
+        other_total: usize,
+        patterns: Vec<FrequencyChange>,
+        facets: Vec<FacetComparison>,
+    }
+
+    /// Pairs up the counts of both sessions; values of `other` are merged into
+    /// the equal value of `base` or, failing that, the first one `same` accepts.
+    fn frequency_changes(
+        base: Vec<(String, usize)>,
+        base_total: usize,
+        other: Vec<(String, usize)>,
+        other_total: usize,
+        same: Option<fn(&str, &str) -> bool>,
+    ) -> Vec<FrequencyChange> {
+        let share = |count: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                count as f64 / total as f64
+            }
+        };
+        let mut changes = base
+            .into_iter()
+            .map(|(value, base_count)| FrequencyChange {
+                value,
+                base_count,
+                other_count: 0,
+                base_share: share(base_count, base_total),
+                other_share: 0.0,
+                ratio: None,
+            })
+            .collect::<Vec<FrequencyChange>>();
+        // Values are joined by key; `same` is only tried for values without an
+        // exact match, against those with as many words
+        let words = |value: &str| value.split(' ').count();
+        let mut positions: HashMap<String, usize> =
+            changes.iter().enumerate().map(|(i, change)| (change.value.clone(), i)).collect();
+        let mut shapes: HashMap<usize, Vec<usize>> = HashMap::new();
+        if same.is_some() {
+            for (i, change) in changes.iter().enumerate() {
+                shapes.entry(words(&change.value)).or_default().push(i);
+            }
+        }
+        for (value, count) in other {
+            let position = positions.get(&value).copied().or_else(|| {
+                let same = same?;
+                shapes
+                    .get(&words(&value))?
+                    .iter()
+                    .copied()
+                    .find(|&i| same(&changes[i].value, &value))
+            });
+            match position {
+                Some(position) => changes[position].other_count += count,
+                None => {
+                    if same.is_some() {
+                        shapes.entry(words(&value)).or_default().push(changes.len());
+                    }
+                    positions.insert(value.clone(), changes.len());
+                    changes.push(FrequencyChange {
+                        value,
+                        base_count: 0,
+                        other_count: count,
+                        base_share: 0.0,
+                        other_share: 0.0,
+                        ratio: None,
+                    });
+                }
+            }
+        }
+
+        for change in changes.iter_mut() {
+            change.other_share = share(change.other_count, other_total);
+            if change.base_count > 0 && change.other_count > 0 {
+                change.ratio = Some(change.other_share / change.base_share);
+            }
+        }
+        let significance = |change: &FrequencyChange| match change.ratio {
+            Some(ratio) => (0, ratio.ln().abs()),
+            None => (1, change.base_share.max(change.other_share)),
+        };
+        changes.sort_by(|a, b| {
+            let (a, b) = (significance(a), significance(b));
+            b.0.cmp(&a.0).then(b.1.total_cmp(&a.1))
+        });
+        changes
+    }
+
+    /// Counts per facet value key (as in `FacetValue::value`) of a field.
+    fn value_counts(session: &StructuredLoggingSession, property: &str) -> Vec<(String, usize)> {
+        let mut counts: Vec<(String, usize)> = match session.facet_index.get(property) {
+            // Facets already keep the positions of each value
+            Some(index) => index
+                .iter()
+                .filter(|(_, seqs)| !seqs.is_empty())
+                .map(|(key, seqs)| (key.clone(), seqs.len()))
+                .collect(),
+            None => {
+                let mut counts: HashMap<String, usize> = HashMap::new();
+                for entry in session.entries.iter() {
+                    if let Some(value) = entry.get(property) {
+                        *counts.entry(serde_json::to_string(&value).unwrap()).or_insert(0) += 1;
+                    }
+                }
+                counts.into_iter().collect()
+            }
+        };
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts
+    }
+
+    /// Diffs two sessions, e.g. a healthy and a broken replica or before and
+    /// after a deploy: message templates and the values of `properties`
+    /// (by default the facets of both sessions) that only occur in one of
+    /// them or whose relative frequency changed.
+    #[tauri::command]
+    pub async fn compare_structured_logging_sessions(
+        base_session_id: String,
+        other_session_id: String,
+        properties: Option<Vec<String>>,
+    ) -> Result<SessionComparison, StructuredLoggingError> {
+        info!(
+            "Comparing structured logging sessions: {} and {}",
+            base_session_id, other_session_id
+        );
+        let sessions = STRUCTURED_LOGGING_SESSIONS.lock().unwrap();
+        let Some(base) = sessions.as_ref().and_then(|s| s.get(&base_session_id)) else {
+            return Err(StructuredLoggingError::session_not_found(&base_session_id));
+        };
+        let Some(other) = sessions.as_ref().and_then(|s| s.get(&other_session_id)) else {
+            return Err(StructuredLoggingError::session_not_found(&other_session_id));
+        };
+
+        let pattern_counts = |session: &StructuredLoggingSession| {
+            session
+                .patterns
+                .clusters()
+                .into_iter()
+                .map(|cluster| (cluster.template, cluster.count))
+                .collect::<Vec<(String, usize)>>()
+        };
+        let patterns = frequency_changes(
+            pattern_counts(base),
+            base.entries.len(),
+            pattern_counts(other),
+            other.entries.len(),
+            Some(patterns::same_shape),
+        );
+
+        let properties = properties.unwrap_or_else(|| {
+            let mut properties = Vec::new();
+            for facet in base.facets.iter().chain(other.facets.iter()) {
+                if !properties.contains(&facet.property) {
+                    properties.push(facet.property.clone());
+                }
+            }
+            properties
+        });
+        let facets = properties
+            .into_iter()
+            .map(|property| FacetComparison {
+                values: frequency_changes(
+                    value_counts(base, &property),
+                    base.entries.len(),
+                    value_counts(other, &property),
+                    other.entries.len(),
+                    None,
+                ),
+                property,
+            })
+            .collect();
+
+        return Ok(SessionComparison {
+            base_total: base.entries.len(),
+            other_total: other.entries.len(),
+            patterns,
+            facets,
+        });
+    }
+
+    #[derive(Clone, Debug, PartialEq, serde::Serialize)]
+    pub struct FieldStats {
+        count: usize,
+        min: f64,
+        max: f64,
+        sum: f64,
+        avg: f64,
+        p50: f64,
+        p95: f64,
+        p99: f64,
+    }
+
+    impl FieldStats {
+        /// Returns `None` for an empty slice. Percentiles use the nearest rank.
+        fn of(values: &mut [f64]) -> Option<FieldStats> {
+            if values.is_empty() {
+                return None;
+            }
+            values.sort_by(f64::total_cmp);
+            let count = values.len();
+            let sum: f64 = values.iter().sum();
+            let percentile = |p: f64| {
+                let rank = (p * count as f64).ceil() as usize;
+                values[rank.clamp(1, count) - 1]
+            };
+            Some(FieldStats {
+                count,
+                min: values[0],
+                max: values[count - 1],
+                sum,
+                avg: sum / count as f64,
+                p50: percentile(0.5),
+                p95: percentile(0.95),
+                p99: percentile(0.99),
+            })
+        }
+    }
+
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct Aggregation {
+        field: String,
+        /// `None` when no filtered entry has a numeric value for `field`.
+        stats: Option<FieldStats>,
+        /// Filtered entries whose value for `field` is missing or not a number.
+        skipped: usize,
+        /// Stats per value of the `group_by` field, largest groups first.
+        groups: Vec<AggregationGroup>,
+    }
+
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct AggregationGroup {
+        value: String,
+        stats: FieldStats,
+    }
+
+    /// Numbers, and strings holding one, as access log fields are often text.
+    fn numeric_value(value: &Value) -> Option<f64> {
+        match value {
+            Value::Number(number) => number.as_f64(),
+            Value::String(text) => text.trim().parse::<f64>().ok().filter(|n| n.is_finite()),
+            _ => None,
+        }
+    }
+
+    /// Computes min/max/avg and percentiles of a numeric (dotted) field over
+    /// the filtered entries, optionally grouped by the values of another field,
+    /// e.g. p95 of `response_time` per `status_code`.
+    #[tauri::command]
+    pub async fn get_aggregation_for_structured_logging_session(
+        session_id: String,
+        search_query: String,
+        from: Option<i64>,
+        to: Option<i64>,
+        field: String,
+        group_by: Option<String>,
+    ) -> Result<Aggregation, QueryError> {
+        info!("Getting aggregation for structured logging session: {}", session_id);
+        let query = query::parse(&search_query)?;
+        let mut values = Vec::new();
+        let mut grouped: HashMap<String, Vec<f64>> = HashMap::new();
+        let mut skipped = 0;
+        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .get_mut(&session_id)
+        {
+            for index in get_filtered_indices(session, query.as_ref(), from, to, 0) {
+                let entry = &session.entries[index];
+                let Some(value) = entry.get(&field).and_then(|value| numeric_value(&value)) else {
+                    skipped += 1;
+                    continue;
+                };
+                values.push(value);
+                if let Some(group) = group_by.as_ref().and_then(|group_by| entry.get(group_by)) {
+                    grouped.entry(query::value_to_string(&group)).or_default().push(value);
+                }
+            }
+        }
+
+        let mut groups = grouped
+            .into_iter()
+            .filter_map(|(value, mut values)| {
+                FieldStats::of(&mut values).map(|stats| AggregationGroup { value, stats })
+            })
+            .collect::<Vec<AggregationGroup>>();
+        groups.sort_by(|a, b| {
+            b.stats.count.cmp(&a.stats.count).then_with(|| a.value.cmp(&b.value))
+        });
+
+        return Ok(Aggregation {
+            field,
+            stats: FieldStats::of(&mut values),
+            skipped,
+            groups,
+        });
+    }
+
+    impl QueryTarget for StructuredLogEntry {
+        fn text(&self) -> &str {
+            &self.content
+        }
+
+        fn field(&self, path: &str) -> Option<Cow<'_, Value>> {
+            self.get(path)
+        }
+    }
+
+    /// Sort key of one column of an entry. Keys of different types order as
+    /// booleans < numbers < strings < arrays and objects.
+    #[derive(Debug)]
+    enum SortKey<'a> {
+        Bool(bool),
+        Number(f64),
+        Text(Cow<'a, str>),
+        /// Arrays and objects, by their JSON text.
+        Json(String),
+    }
+
+    impl SortKey<'_> {
+        fn rank(&self) -> u8 {
+            match self {
+                SortKey::Bool(_) => 0,
+                SortKey::Number(_) => 1,
+                SortKey::Text(_) => 2,
+                SortKey::Json(_) => 3,
+            }
+        }
+    }
+
+    impl PartialEq for SortKey<'_> {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == std::cmp::Ordering::Equal
+        }
+    }
+
+    impl Eq for SortKey<'_> {}
+
+    impl PartialOrd for SortKey<'_> {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for SortKey<'_> {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            match (self, other) {
+                (SortKey::Bool(a), SortKey::Bool(b)) => a.cmp(b),
+                (SortKey::Number(a), SortKey::Number(b)) => a.total_cmp(b),
+                (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+                (SortKey::Json(a), SortKey::Json(b)) => a.cmp(b),
+                _ => self.rank().cmp(&other.rank()),
+            }
+        }
+    }
+
+    /// `timestamp` sorts by the parsed time, `content` by the raw line and
+    /// `level` by severity; anything else by the (dotted) field. Missing and
+    /// null values have no key.
+    fn sort_key<'a>(entry: &'a StructuredLogEntry, column: &str) -> Option<SortKey<'a>> {
+        match column {
+            "timestamp" => return entry.time.map(|time| SortKey::Number(time as f64)),
+            "content" => return Some(SortKey::Text(Cow::Borrowed(&entry.content))),
+            "level" => return Some(SortKey::Number(entry.level as u8 as f64)),
+            _ => {}
+        }
+        let key = match entry.get(column)? {
+            Cow::Borrowed(Value::String(text)) => SortKey::Text(Cow::Borrowed(text)),
+            Cow::Owned(Value::String(text)) => SortKey::Text(Cow::Owned(text)),
+            value => match &*value {
+                Value::Null => return None,
+                Value::Bool(b) => SortKey::Bool(*b),
+                Value::Number(number) => SortKey::Number(number.as_f64().unwrap_or(f64::NAN)),
+                _ => SortKey::Json(value.to_string()),
+            },
+        };
+        Some(key)
+    }
+
+    /// Sorts by each column in turn. Entries without a value for a column come
+    /// last in either direction, and ties keep their order so live-tailing
+    /// doesn't reshuffle rows.
+    fn apply_sorting(
+        entries: &VecDeque<StructuredLogEntry>,
+        indices: &mut [usize],
+        sorting: &[SortingState],
+    ) {
+        let mut keyed = indices
+            .iter()
+            .map(|index| (*index, sort_keys(&entries[*index], sorting)))
+            .collect::<Vec<(usize, Vec<Option<SortKey>>)>>();
+
+        keyed.sort_by(|(_, a), (_, b)| compare_sort_keys(a, b, sorting));
+
+        for (index, (sorted, _)) in indices.iter_mut().zip(keyed) {
+            *index = sorted;
+        }
+    }
+
+    fn sort_keys<'a>(
+        entry: &'a StructuredLogEntry,
+        sorting: &[SortingState],
+    ) -> Vec<Option<SortKey<'a>>> {
+        sorting.iter().map(|sort| sort_key(entry, &sort.id)).collect()
+    }
+
+    fn compare_sort_keys(
+        a: &[Option<SortKey>],
+        b: &[Option<SortKey>],
+        sorting: &[SortingState],
+    ) -> std::cmp::Ordering {
+        for ((a, b), sort) in a.iter().zip(b.iter()).zip(sorting) {
+            let order = match (a, b) {
+                (None, None) => std::cmp::Ordering::Equal,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (Some(a), Some(b)) if sort.desc => b.cmp(a),
+                (Some(a), Some(b)) => a.cmp(b),
+            };
+            if order != std::cmp::Ordering::Equal {
+                return order;
+            }
+        }
+        std::cmp::Ordering::Equal
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn entry(data: Value) -> StructuredLogEntry {
+            StructuredLogEntry::new(data.to_string(), String::new(), data)
+        }
+
+        /// Runs a command to completion; they never actually wait on anything.
+        fn block_on<F: std::future::Future>(future: F) -> F::Output {
+            let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+            match std::pin::pin!(future).poll(&mut context) {
+                std::task::Poll::Ready(output) => output,
+                std::task::Poll::Pending => panic!("command did not complete"),
+            }
+        }
+
+        /// Starts a session with one entry per `(path, status)` pair and a
+        /// facet on each of the two fields.
+        fn facet_session(rows: &[(&str, u16)], path: &str, status: &str) -> String {
+            let lines = rows
+                .iter()
+                .map(|(path, status)| format!("- {}", json!({"path": path, "status": status})))
+                .collect();
+            let session_id = block_on(start_structured_logging_session(lines));
+            for (property, match_type) in [("path", path), ("status", status)] {
+                block_on(add_facet_to_structured_logging_session(
+                    session_id.clone(),
+                    property.to_string(),
+                    match_type.to_string(),
+                ));
+            }
+            session_id
+        }
+
+        fn select(session_id: &str, property: &str, value: Value) {
+            block_on(set_filtered_for_facet_value(
+                session_id.to_string(),
+                property.to_string(),
+                value.to_string(),
+                true,
+            ));
+        }
+
+        fn exclude(session_id: &str, property: &str, value: Value) {
+            block_on(set_excluded_for_facet_value(
+                session_id.to_string(),
+                property.to_string(),
+                value.to_string(),
+                true,
+            ));
+        }
+
+        /// The sorted `(path, status)` pairs of the filtered view.
+        fn filtered(session_id: &str) -> Vec<(String, u64)> {
+            let result = block_on(get_filtered_data_for_structured_logging_session(
+                session_id.to_string(),
+                String::new(),
+                Vec::new(),
+                None,
+                None,
+                None,
+                None,
+            ))
+            .unwrap();
+            let mut rows = result
+                .entries
+                .iter()
+                .map(|e| {
+                    let path = e.data["path"].as_str().unwrap().to_string();
+                    (path, e.data["status"].as_u64().unwrap())
+                })
+                .collect::<Vec<(String, u64)>>();
+            rows.sort();
+            rows
+        }
+
+        fn rows(rows: &[(&str, u64)]) -> Vec<(String, u64)> {
+            rows.iter().map(|(path, status)| (path.to_string(), *status)).collect()
+        }
+
+        const ROWS: [(&str, u16); 5] =
+            [("/", 200), ("/", 500), ("/api", 200), ("/api", 404), ("/healthz", 200)];
+
+        fn with_session<T>(
+            session_id: &str,
+            f: impl FnOnce(&mut StructuredLoggingSession) -> T,
+        ) -> T {
+            let mut sessions = STRUCTURED_LOGGING_SESSIONS.lock().unwrap();
+            f(sessions.as_mut().unwrap().get_mut(session_id).unwrap())
+        }
+
+        fn append(session_id: &str, lines: &[String]) {
+            let multiline = with_session(session_id, |session| session.multiline.clone());
+            ingest_lines(session_id, &multiline, &lines.join("\n"), false);
+        }
+
+        /// Checks every facet's index and value totals against a rebuild from
+        /// scratch.
+        fn assert_index_matches_rescan(session_id: &str) {
+            let sessions = STRUCTURED_LOGGING_SESSIONS.lock().unwrap();
+            let session = &sessions.as_ref().unwrap()[session_id];
+            let totals = |facet: &Facet| {
+                facet
+                    .values
+                    .iter()
+                    .filter(|v| v.total > 0)
+                    .map(|v| (v.value.clone(), v.total))
+                    .collect::<HashMap<String, u32>>()
+            };
+            for facet in session.facets.iter() {
+                let mut rescanned = Facet {
+                    property: facet.property.clone(),
+                    match_type: facet.match_type.clone(),
+                    values: Vec::new(),
+                };
+                let mut index = HashMap::new();
+                index_facet(&mut rescanned, &mut index, &session.entries, session.first_seq, 0);
+                assert_eq!(session.facet_index[&facet.property], index, "{}", facet.property);
+                assert_eq!(totals(facet), totals(&rescanned), "{}", facet.property);
+            }
+        }
+
+        #[test]
+        fn facet_values_are_ored_within_a_facet() {
+            let session_id = facet_session(&ROWS, "AND", "AND");
+            select(&session_id, "path", json!("/"));
+            select(&session_id, "path", json!("/healthz"));
+            assert_eq!(filtered(&session_id), rows(&[("/", 200), ("/", 500), ("/healthz", 200)]));
+        }
+
+        #[test]
+        fn facets_combine_independently_of_order() {
+            for (path, status) in [("AND", "AND"), ("AND", "OR"), ("OR", "AND")] {
+                let session_id = facet_session(&ROWS, path, status);
+                select(&session_id, "path", json!("/api"));
+                select(&session_id, "status", json!(200));
+                assert_eq!(filtered(&session_id), rows(&[("/api", 200)]), "{} {}", path, status);
+            }
+
+            let session_id = facet_session(&ROWS, "OR", "OR");
+            select(&session_id, "path", json!("/api"));
+            select(&session_id, "status", json!(500));
+            assert_eq!(
+                filtered(&session_id),
+                rows(&[("/", 500), ("/api", 200), ("/api", 404)])
+            );
+        }
+
+        #[test]
+        fn excluded_values_are_hidden() {
+            let session_id = facet_session(&ROWS, "OR", "OR");
+            exclude(&session_id, "path", json!("/healthz"));
+            assert_eq!(
+                filtered(&session_id),
+                rows(&[("/", 200), ("/", 500), ("/api", 200), ("/api", 404)])
+            );
+
+            select(&session_id, "status", json!(200));
+            assert_eq!(filtered(&session_id), rows(&[("/", 200), ("/api", 200)]));
+
+            // Selecting an excluded value includes it again
+            select(&session_id, "path", json!("/healthz"));
+            assert_eq!(
+                filtered(&session_id),
+                rows(&[("/", 200), ("/api", 200), ("/healthz", 200)])
+            );
+        }
+
+        #[test]
+        fn facet_index_matches_a_rescan() {
+            let session_id = facet_session(&ROWS, "OR", "AND");
+            let lines = |status_offset: u16| {
+                ROWS.iter()
+                    .map(|(path, status)| {
+                        json!({"path": path, "status": status + status_offset}).to_string()
+                    })
+                    .collect::<Vec<String>>()
+            };
+
+            append(&session_id, &lines(0));
+            append(&session_id, &lines(1));
+            assert_index_matches_rescan(&session_id);
+
+            // A facet added later indexes what is already there, then keeps up
+            block_on(add_facet_to_structured_logging_session(
+                session_id.clone(),
+                "level".to_string(),
+                "OR".to_string(),
+            ));
+            assert_index_matches_rescan(&session_id);
+            append(&session_id, &lines(100));
+            assert_index_matches_rescan(&session_id);
+
+            // And stays in sync as old entries are evicted
+            with_session(&session_id, |session| session.retention.max_entries = Some(7));
+            append(&session_id, &lines(200));
+            assert_index_matches_rescan(&session_id);
+            assert_eq!(filtered(&session_id).len(), 7);
+        }
+
+        /// The non-zero totals of a facet's values, by value.
+        fn totals(session_id: &str, property: &str) -> Vec<(Value, u32)> {
+            let facet = block_on(get_facets_for_structured_logging_session(session_id.to_string()))
+                .into_iter()
+                .find(|f| f.property == property)
+                .unwrap();
+            let mut totals = facet
+                .values
+                .into_iter()
+                .filter(|v| v.total > 0)
+                .map(|v| (serde_json::from_str(&v.value).unwrap(), v.total))
+                .collect::<Vec<(Value, u32)>>();
+            totals.sort_by_key(|(value, _)| value.to_string());
+            totals
+        }
+
+        fn set_retention(session_id: &str, max_entries: Option<usize>, max_bytes: Option<usize>) {
+            block_on(set_retention_policy_for_structured_logging_session(
+                session_id.to_string(),
+                RetentionPolicy {
+                    max_entries,
+                    max_bytes,
+                    max_age_seconds: None,
+                },
+            ));
+        }
+
+        #[test]
+        fn retention_evicts_by_count() {
+            let session_id = facet_session(&ROWS, "OR", "OR");
+            select(&session_id, "path", json!("/"));
+            assert_eq!(filtered(&session_id), rows(&[("/", 200), ("/", 500)]));
+
+            set_retention(&session_id, Some(3), None);
+            // The selected value stays, but nothing matches it anymore
+            assert_eq!(filtered(&session_id), rows(&[]));
+            assert_eq!(totals(&session_id, "path"), [(json!("/api"), 2), (json!("/healthz"), 1)]);
+            assert_eq!(totals(&session_id, "status"), [(json!(200), 2), (json!(404), 1)]);
+
+            select(&session_id, "path", json!("/api"));
+            assert_eq!(filtered(&session_id), rows(&[("/api", 200), ("/api", 404)]));
+            let stats = block_on(get_eviction_stats_for_structured_logging_session(session_id));
+            assert_eq!(stats.evicted_entries, 2);
+        }
+
+        #[test]
+        fn retention_evicts_by_bytes() {
+            let session_id = facet_session(&ROWS, "OR", "OR");
+            let last_two = with_session(&session_id, |session| {
+                session.entries.iter().rev().take(2).map(|e| e.content.len()).sum::<usize>()
+            });
+
+            set_retention(&session_id, None, Some(last_two));
+            assert_eq!(filtered(&session_id), rows(&[("/api", 404), ("/healthz", 200)]));
+            assert_eq!(totals(&session_id, "path"), [(json!("/api"), 1), (json!("/healthz"), 1)]);
+            assert_eq!(totals(&session_id, "status"), [(json!(200), 1), (json!(404), 1)]);
+            assert_eq!(with_session(&session_id, |session| session.bytes), last_two);
+        }
+
+        #[test]
+        fn retention_evicts_by_age() {
+            let session_id = facet_session(&ROWS, "OR", "OR");
+            with_session(&session_id, |session| {
+                for entry in session.entries.range_mut(..2) {
+                    entry.received_at -= Duration::from_secs(120);
+                }
+                session.retention.max_age_seconds = Some(60);
+                session.apply_retention();
+            });
+
+            assert_eq!(
+                filtered(&session_id),
+                rows(&[("/api", 200), ("/api", 404), ("/healthz", 200)])
+            );
+            assert_eq!(totals(&session_id, "path"), [(json!("/api"), 2), (json!("/healthz"), 1)]);
+            assert_eq!(totals(&session_id, "status"), [(json!(200), 2), (json!(404), 1)]);
+        }
+
+        #[test]
+        fn detect_levels() {
+            let level = |content: &str, data: Value| detect_level(content, &data);
+
+            assert_eq!(level("", json!({"level": "WARNING"})), Level::Warn);
+            assert_eq!(level("", json!({"severity": "crit"})), Level::Fatal);
+            assert_eq!(level("", json!({"log": {"level": "dbg"}})), Level::Debug);
+            // Bunyan and pino
+            for (number, expected) in [(10, Level::Trace), (30, Level::Info), (50, Level::Error)] {
+                assert_eq!(level("", json!({"level": number})), expected);
+            }
+            assert_eq!(level("", json!({"level": 60})), Level::Fatal);
+
+            let klog = "W0412 10:00:00.000000       1 main.go:42] slow";
+            assert_eq!(level(klog, json!({"message": klog})), Level::Warn);
+            assert_eq!(level("I0412", json!({})), Level::Unknown);
+
+            assert_eq!(level("", json!({"status": 200})), Level::Info);
+            assert_eq!(level("", json!({"status_code": 404})), Level::Warn);
+            assert_eq!(level("", json!({"statusCode": 503})), Level::Error);
+            assert_eq!(level("", json!({"status": 42})), Level::Unknown);
+
+            assert_eq!(level("[ERROR] connection lost", json!({})), Level::Error);
+            assert_eq!(level("2024-01-02 03:04:05 WARN low disk", json!({})), Level::Warn);
+            assert_eq!(level("an error in the first words", json!({})), Level::Unknown);
+            assert_eq!(level("", json!({"level": "verbose", "status": 500})), Level::Error);
+        }
+
+        #[test]
+        fn parse_times() {
+            assert_eq!(parse_time("2024-01-02T03:04:05Z"), Some(1_704_164_645_000));
+            assert_eq!(parse_time("2024-01-02T05:04:05.678+02:00"), Some(1_704_164_645_678));
+            assert_eq!(parse_time(" 2024-01-02T03:04:05.123456789Z"), Some(1_704_164_645_123));
+            assert_eq!(parse_time("2024-01-02 03:04:05"), Some(1_704_164_645_000));
+            assert_eq!(parse_time("02/Jan/2024:04:04:05 +0100"), Some(1_704_164_645_000));
+            assert_eq!(parse_time("-"), None);
+
+            let now = DateTime::parse_from_rfc3339("2024-06-01T00:00:00Z").unwrap().to_utc();
+            let klog = "I0102 03:04:05.000100    1 main.go:42] started";
+            assert_eq!(parse_klog_time(klog, now), Some(1_704_164_645_000));
+            assert_eq!(parse_klog_time("I0102 started", now), None);
+            // Just after new year, December is last year
+            let now = DateTime::parse_from_rfc3339("2025-01-01T00:10:00Z").unwrap().to_utc();
+            let klog = "E1231 23:59:59.500000    1 main.go:42] failed";
+            assert_eq!(parse_klog_time(klog, now), Some(1_735_689_599_500));
+
+            for (data, time) in [
+                (json!({"ts": 1_704_164_645}), 1_704_164_645_000),
+                (json!({"ts": 1_704_164_645.25}), 1_704_164_645_250),
+                (json!({"time": 1_704_164_645_678_i64}), 1_704_164_645_678),
+                (json!({"ts": 1_704_164_645_678_901_i64}), 1_704_164_645_678),
+                (json!({"ts": 1_704_164_645_678_901_234_i64}), 1_704_164_645_678),
+                (json!({"@timestamp": "2024-01-02T03:04:05Z"}), 1_704_164_645_000),
+            ] {
+                assert_eq!(find_time_in_data(&data), Some(time), "{}", data);
+            }
+        }
+
+        #[test]
+        fn histogram_buckets() {
+            let lines = ["00:00:00", "00:00:29", "00:01:10", "00:04:59"]
+                .iter()
+                .zip(["info", "error", "info", "info"])
+                .map(|(time, level)| {
+                    format!("2024-01-02T{}Z {}", time, json!({"level": level}))
+                })
+                .collect();
+            let session_id = block_on(start_structured_logging_session(lines));
+            let histogram = |from, to, max_buckets| {
+                block_on(get_histogram_for_structured_logging_session(
+                    session_id.clone(),
+                    String::new(),
+                    from,
+                    to,
+                    Some("level".to_string()),
+                    max_buckets,
+                ))
+                .unwrap()
+            };
+
+            // Five minutes in 10 buckets of 30 seconds
+            let h = histogram(None, None, Some(10));
+            assert_eq!(h.bucket_size, 30_000);
+            assert_eq!(h.buckets[0].start, 1_704_153_600_000);
+            let totals = h.buckets.iter().map(|b| b.total).collect::<Vec<u32>>();
+            assert_eq!(totals, [2, 0, 1, 0, 0, 0, 0, 0, 0, 1]);
+            assert_eq!(h.buckets[0].counts["info"], 1);
+            assert_eq!(h.buckets[0].counts["error"], 1);
+
+            // Ranges too wide for the largest size stay within the cap
+            let h = histogram(Some(0), Some(i64::MAX), Some(u32::MAX));
+            assert!(h.buckets.len() <= MAX_HISTOGRAM_BUCKETS as usize + 1);
+            assert_eq!(h.buckets.iter().map(|b| b.total).sum::<u32>(), 4);
+            let h = histogram(Some(i64::MIN), Some(i64::MAX), None);
+            assert!(h.buckets.len() <= 61);
+        }
+
+        #[test]
+        fn continuation_rules() {
+            let rules = MultilineRules::new(MultilineConfig::default()).unwrap();
+            for line in [
+                "\tat com.example.Foo.bar(Foo.java:10)",
+                "  File \"app.py\"",
+                "at x.y",
+                "\t... 3 more",
+                "Caused by: java.io.IOException",
+                "Traceback (most recent call last):",
+            ] {
+                assert!(rules.is_continuation(line), "{}", line);
+            }
+            // JSON records and blank lines never continue an entry
+            for line in ["ERROR failed", "ValueError: bad", "   ", "", "{\"msg\": \"at x\"}"] {
+                assert!(!rules.is_continuation(line), "{}", line);
+            }
+
+            let rules = MultilineRules::new(MultilineConfig {
+                require_timestamp: true,
+                ..MultilineConfig::default()
+            })
+            .unwrap();
+            assert!(rules.is_continuation("ValueError: bad input"));
+            for line in ["2024-01-02 03:04:05 ok", "I0102 03:04:05.0 ok", "Jan  2 03:04:05 ok"] {
+                assert!(!rules.is_continuation(line), "{}", line);
+            }
+
+            let rules = MultilineRules::new(MultilineConfig {
+                enabled: false,
+                ..MultilineConfig::default()
+            })
+            .unwrap();
+            assert!(!rules.is_continuation("\tat com.example.Foo.bar(Foo.java:10)"));
+        }
+
+        /// Feeds `lines`, prefixed with `kubectl --timestamps` style times, to a
+        /// new session in two batches split at every possible point and checks
+        /// the entries come out as `expected` each time.
+        fn assert_stitched(config: MultilineConfig, lines: &[&str], expected: &[&str]) {
+            let lines = lines
+                .iter()
+                .enumerate()
+                .map(|(i, line)| format!("2024-01-02T03:04:{:02}Z {}", i, line))
+                .collect::<Vec<String>>();
+            for split in 0..lines.len() {
+                let session_id = block_on(start_structured_logging_session(Vec::new()));
+                block_on(set_multiline_config_for_structured_logging_session(
+                    session_id.clone(),
+                    config.clone(),
+                ))
+                .unwrap();
+                for property in ["message", "level", "pattern_id"] {
+                    block_on(add_facet_to_structured_logging_session(
+                        session_id.clone(),
+                        property.to_string(),
+                        "OR".to_string(),
+                    ));
+                }
+                append(&session_id, &lines[..split]);
+                append(&session_id, &lines[split..]);
+
+                let contents = with_session(&session_id, |session| {
+                    let bytes = session.entries.iter().map(|e| e.content.len()).sum::<usize>();
+                    assert_eq!(session.bytes, bytes);
+                    session.entries.iter().map(|e| e.content.clone()).collect::<Vec<String>>()
+                });
+                assert_eq!(contents, expected, "split at {}", split);
+                assert_index_matches_rescan(&session_id);
+            }
+        }
+
+        #[test]
+        fn stitches_java_tracebacks() {
+            let lines = [
+                "ERROR Request failed: java.lang.IllegalStateException: boom",
+                "\tat com.example.Handler.handle(Handler.java:42)",
+                "\tat com.example.Server.run(Server.java:7)",
+                "Caused by: java.io.IOException: closed",
+                "\tat com.example.Conn.read(Conn.java:3)",
+                "\t... 2 more",
+                "INFO Request served",
+            ];
+            assert_stitched(
+                MultilineConfig::default(),
+                &lines,
+                &[lines[..6].join("\n").as_str(), lines[6]],
+            );
+        }
+
+        #[test]
+        fn stitches_python_tracebacks() {
+            let lines = [
+                "2024-01-02 03:04:05,123 ERROR Unhandled exception",
+                "Traceback (most recent call last):",
+                "  File \"app.py\", line 3, in <module>",
+                "    main()",
+                "ValueError: bad input",
+                "2024-01-02 03:04:06,000 INFO Retrying",
+            ];
+            // The exception line only continues the entry when entries must
+            // start with a timestamp
+            assert_stitched(
+                MultilineConfig::default(),
+                &lines,
+                &[lines[..4].join("\n").as_str(), lines[4], lines[5]],
+            );
+            let config = MultilineConfig {
+                require_timestamp: true,
+                ..MultilineConfig::default()
+            };
+            assert_stitched(config, &lines, &[lines[..5].join("\n").as_str(), lines[5]]);
+        }
+
+        #[test]
+        fn saved_session_round_trip() {
+            let dir = std::env::temp_dir().join(format!("log_sessions_{}", Uuid::new_v4()));
+            let listed = |dir: &Path| {
+                list_saved_sessions(dir)
+                    .unwrap()
+                    .into_iter()
+                    .map(|info| (info.id, info.name, info.open))
+                    .collect::<Vec<(String, String, bool)>>()
+            };
+            assert_eq!(listed(&dir), []);
+
+            let session_id = facet_session(&ROWS, "OR", "OR");
+            select(&session_id, "path", json!("/api"));
+            let view_state = ViewState {
+                search_query: "status>=400".to_string(),
+                sorting: vec![SortingState { id: "status".to_string(), desc: true }],
+            };
+            with_session(&session_id, |session| {
+                let ViewState { search_query, sorting } = view_state.clone();
+                session.update_view(search_query, sorting, None, None).unwrap();
+            });
+            let info = save_session(&dir, session_id.clone(), "first".to_string()).unwrap();
+            assert_eq!((info.name.as_str(), info.open), ("first", true));
+            // Entries arriving after saving are kept too
+            append(&session_id, &[json!({"path": "/api", "status": 500}).to_string()]);
+            block_on(end_structured_logging_session(session_id.clone()));
+            assert_eq!(listed(&dir), [(session_id.clone(), "first".to_string(), false)]);
+
+            rename_saved_session(&dir, session_id.clone(), "renamed".to_string()).unwrap();
+            assert_eq!(listed(&dir), [(session_id.clone(), "renamed".to_string(), false)]);
+            assert!(!dir.join(&session_id).join("meta.json.tmp").exists());
+
+            assert_eq!(open_saved_session(&dir, session_id.clone()).unwrap(), session_id);
+            assert_eq!(listed(&dir), [(session_id.clone(), "renamed".to_string(), true)]);
+            assert_eq!(
+                block_on(get_view_state_for_structured_logging_session(session_id.clone())),
+                view_state
+            );
+            assert_eq!(
+                filtered(&session_id),
+                rows(&[("/api", 200), ("/api", 404), ("/api", 500)])
+            );
+            rename_saved_session(&dir, session_id.clone(), "open".to_string()).unwrap();
+            assert_eq!(listed(&dir), [(session_id.clone(), "open".to_string(), true)]);
+
+            // Deleting keeps the open session around, unsaved
+            delete_saved_session(&dir, session_id.clone()).unwrap();
+            assert_eq!(listed(&dir), []);
+            assert!(with_session(&session_id, |session| session.storage.is_none()));
+            assert_eq!(filtered(&session_id).len(), 3);
+
+            assert!(open_saved_session(&dir, "../elsewhere".to_string()).is_err());
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn saved_session_with_a_torn_last_line() {
+            let dir = std::env::temp_dir().join(format!("log_sessions_{}", Uuid::new_v4()));
+            let session_id = facet_session(&ROWS, "OR", "OR");
+            save_session(&dir, session_id.clone(), "torn".to_string()).unwrap();
+            block_on(end_structured_logging_session(session_id.clone()));
+            let path = dir.join(&session_id).join(ENTRIES_FILE);
+            let saved = fs::read_to_string(&path).unwrap();
+            let append_raw = |bytes: &[u8]| {
+                OpenOptions::new().append(true).open(&path).unwrap().write_all(bytes).unwrap();
+            };
+            append_raw(b"{\"timestamp\": \"\", \"content\": \"{\\\"pa");
+
+            assert_eq!(open_saved_session(&dir, session_id.clone()).unwrap(), session_id);
+            assert_eq!(filtered(&session_id).len(), ROWS.len());
+            assert_eq!(fs::read_to_string(&path).unwrap(), saved);
+            // Entries saved after the cut start on a line of their own
+            append(&session_id, &[json!({"path": "/api", "status": 500}).to_string()]);
+            block_on(end_structured_logging_session(session_id.clone()));
+            open_saved_session(&dir, session_id.clone()).unwrap();
+            assert_eq!(filtered(&session_id).len(), ROWS.len() + 1);
+            block_on(end_structured_logging_session(session_id.clone()));
+
+            // Anything but the last line is still an error
+            fs::write(&path, format!("not json\n{}", saved)).unwrap();
+            assert!(open_saved_session(&dir, session_id).is_err());
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        fn log_view(id: &str, scope: Option<&str>, facets: Value) -> LogView {
+            serde_json::from_value(json!({
+                "id": id,
+                "name": "errors",
+                "scope": scope,
+                "facets": facets,
+                "search_query": "status>=400",
+                "sorting": [{"id": "status", "desc": true}],
+                "visible_columns": ["path"],
+            }))
+            .unwrap()
+        }
+
+        fn facet_values(view: &LogView) -> Vec<(&str, &[String], &[String])> {
+            view.facets
+                .iter()
+                .map(|f| (f.property.as_str(), f.selected.as_slice(), f.excluded.as_slice()))
+                .collect()
+        }
+
+        #[test]
+        fn log_views_round_trip() {
+            let dir = std::env::temp_dir().join(format!("log_views_{}", Uuid::new_v4()));
+            let path = dir.join("log_views.json");
+            let (root, api, ok, not_found) = ("\"/\"", "\"/api\"", "200", "404");
+            let keys = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+            // Repeated and contradicting values and facets are normalized on save
+            let view = save_view(
+                &path,
+                log_view(
+                    "",
+                    None,
+                    json!([
+                        {"property": "path", "match_type": "OR",
+                            "selected": [api, api, root], "excluded": [root]},
+                        {"property": "status", "match_type": "OR", "selected": [ok]},
+                        {"property": "status", "match_type": "OR",
+                            "selected": [], "excluded": [not_found]},
+                    ]),
+                ),
+            )
+            .unwrap();
+            assert!(!view.id.is_empty());
+            assert_eq!(
+                facet_values(&view),
+                [
+                    ("path", &keys(&[api])[..], &keys(&[root])[..]),
+                    ("status", &[][..], &keys(&[not_found])[..]),
+                ]
+            );
+
+            // Selecting a value the stored view excludes makes it selected
+            let view = save_view(
+                &path,
+                log_view(
+                    &view.id,
+                    None,
+                    json!([
+                        {"property": "path", "match_type": "OR",
+                            "selected": [api, root], "excluded": [root]},
+                        {"property": "status", "match_type": "OR",
+                            "selected": [], "excluded": [not_found]},
+                    ]),
+                ),
+            )
+            .unwrap();
+            assert_eq!(facet_values(&view)[0], ("path", &keys(&[api, root])[..], &[][..]));
+
+            let scoped = save_view(&path, log_view("", Some("workload"), json!([]))).unwrap();
+            let listed = |scope: Option<&str>| {
+                list_views(&path, scope.map(str::to_string))
+                    .unwrap()
+                    .into_iter()
+                    .map(|v| v.id)
+                    .collect::<Vec<String>>()
+            };
+            assert_eq!(listed(None), std::slice::from_ref(&view.id));
+            assert_eq!(listed(Some("workload")), [view.id.clone(), scoped.id.clone()]);
+
+            // Applying restores the facets, the query and the sorting
+            let session_id = facet_session(&ROWS, "AND", "AND");
+            select(&session_id, "status", json!(500));
+            apply_view(&path, session_id.clone(), view.id.clone()).unwrap();
+            assert_eq!(
+                block_on(get_view_state_for_structured_logging_session(session_id.clone())),
+                ViewState {
+                    search_query: "status>=400".to_string(),
+                    sorting: vec![SortingState { id: "status".to_string(), desc: true }],
+                }
+            );
+            assert_eq!(
+                filtered(&session_id),
+                rows(&[("/", 200), ("/", 500), ("/api", 200)])
+            );
+            assert!(apply_view(&path, session_id, "missing".to_string()).is_err());
+
+            delete_view(&path, scoped.id).unwrap();
+            assert_eq!(listed(Some("workload")), [view.id]);
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        fn contents(session_id: &str) -> Vec<String> {
+            with_session(session_id, |session| {
+                session.entries.iter().map(|e| e.content.clone()).collect()
+            })
+        }
+
+        #[test]
+        fn incomplete_lines_wait_for_the_rest() {
+            let session_id = block_on(start_structured_logging_session(Vec::new()));
+            add_data(&session_id, "2024-01-02T03:04:05Z {\"msg\": \"split ".to_string());
+            assert_eq!(contents(&session_id), Vec::<String>::new());
+            add_data(&session_id, "across\"}\n2024-01-02T03:04:06Z never".to_string());
+            assert_eq!(contents(&session_id), ["{\"msg\": \"split across\"}"]);
+            let msg = with_session(&session_id, |session| session.entries[0].data["msg"].clone());
+            assert_eq!(msg, "split across");
+
+            // A trailing line that is never terminated comes in when flushed
+            add_data(&session_id, " terminated".to_string());
+            assert_eq!(contents(&session_id).len(), 1);
+            flush_pending(&session_id);
+            assert_eq!(contents(&session_id)[1], "never terminated");
+
+            // Overly long lines aren't held back
+            let long = "x".repeat(MAX_PENDING_BYTES + 1);
+            add_data(&session_id, format!("2024-01-02T03:04:07Z {}", long));
+            assert_eq!(contents(&session_id)[2], long);
+        }
+
+        #[test]
+        fn ending_a_session_flushes_incomplete_lines() {
+            let dir = std::env::temp_dir().join(format!("log_sessions_{}", Uuid::new_v4()));
+            let session_id = block_on(start_structured_logging_session(Vec::new()));
+            save_session(&dir, session_id.clone(), "pending".to_string()).unwrap();
+            let data = "2024-01-02T03:04:05Z done\n2024-01-02T03:04:06Z partial";
+            add_data(&session_id, data.to_string());
+            block_on(end_structured_logging_session(session_id.clone()));
+
+            open_saved_session(&dir, session_id.clone()).unwrap();
+            assert_eq!(contents(&session_id), ["done", "partial"]);
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn pagination_bounds() {
+            let session_id = facet_session(&ROWS, "OR", "OR");
+            let page = |offset: usize, limit: Option<usize>| {
+                let result = block_on(get_filtered_data_for_structured_logging_session(
+                    session_id.clone(),
+                    String::new(),
+                    Vec::new(),
+                    Some(offset),
+                    limit,
+                    None,
+                    None,
+                ))
+                .unwrap();
+                (result.entries.len(), result.offset, result.filtered)
+            };
+
+            assert_eq!(page(0, Some(2)), (2, 0, 5));
+            assert_eq!(page(3, Some(10)), (2, 3, 5));
+            assert_eq!(page(2, Some(0)), (0, 2, 5));
+            assert_eq!(page(2, None), (3, 2, 5));
+            assert_eq!(page(7, Some(2)), (0, 5, 5));
+        }
+
+        #[test]
+        fn view_cache_follows_appends_and_evictions() {
+            let session_id = facet_session(&ROWS, "OR", "OR");
+            exclude(&session_id, "status", json!(404));
+            set_retention(&session_id, Some(6), None);
+            let view = |session: &mut StructuredLoggingSession| {
+                let sorting = vec![SortingState { id: "path".to_string(), desc: true }];
+                session.update_view("status<500".to_string(), sorting, None, None).unwrap();
+                session.view_cache.as_ref().unwrap().indices.clone()
+            };
+            with_session(&session_id, view);
+
+            append(
+                &session_id,
+                &[
+                    json!({"path": "/api", "status": 200}).to_string(),
+                    json!({"path": "/b", "status": 404}).to_string(),
+                    json!({"path": "/", "status": 201}).to_string(),
+                ],
+            );
+            let (cached, fresh) = with_session(&session_id, |session| {
+                let cached = session.view_cache.as_ref().map(|view| view.indices.clone());
+                session.invalidate_view();
+                (cached, view(session))
+            });
+            assert_eq!(cached, Some(fresh));
+        }
+
+        #[test]
+        fn field_stats() {
+            assert_eq!(FieldStats::of(&mut []), None);
+
+            let mut values = (1..=100).rev().map(f64::from).collect::<Vec<f64>>();
+            let stats = FieldStats::of(&mut values).unwrap();
+            assert_eq!(stats.count, 100);
+            assert_eq!((stats.min, stats.max, stats.avg), (1.0, 100.0, 50.5));
+            assert_eq!((stats.p50, stats.p95, stats.p99), (50.0, 95.0, 99.0));
+
+            assert_eq!(numeric_value(&json!("0.25")), Some(0.25));
+            assert_eq!(numeric_value(&json!("-")), None);
+        }
+
+        /// Streams one line per JSON record, a second apart, into a new session.
+        fn timed_session(records: &[Value]) -> String {
+            let lines = records
+                .iter()
+                .enumerate()
+                .map(|(i, record)| format!("2024-01-02T03:04:{:02}Z {}", i, record))
+                .collect::<Vec<String>>();
+            let session_id = block_on(start_structured_logging_session(Vec::new()));
+            append(&session_id, &lines);
+            session_id
+        }
+
+        fn export(session_id: &str, format: ExportFormat, columns: Option<&[&str]>) -> String {
+            let path = std::env::temp_dir().join(format!("export_{}", Uuid::new_v4()));
+            let result = block_on(export_structured_logging_session(
+                session_id.to_string(),
+                String::new(),
+                Vec::new(),
+                None,
+                None,
+                ExportOptions {
+                    path: path.to_string_lossy().to_string(),
+                    format,
+                    columns: columns.map(|c| c.iter().map(|c| c.to_string()).collect()),
+                },
+            ))
+            .unwrap();
+            assert_eq!(result.skipped, 0);
+            let exported = fs::read_to_string(&path).unwrap();
+            fs::remove_file(path).unwrap();
+            exported
+        }
+
+        #[test]
+        fn nested_columns() {
+            let session_id = timed_session(&[
+                json!({"http": {"request": {"method": "GET"}, "status": 200}, "tags": ["a", "b"]}),
+                json!({"http": {"status": "503"}, "user": null}),
+            ]);
+
+            let columns = get_column_info_for_structured_logging_session(session_id.clone());
+            let columns = block_on(columns)
+                .into_iter()
+                .map(|c| (c.path, c.count, c.types))
+                .collect::<Vec<(String, usize, Vec<ColumnType>)>>();
+            let column = |path: &str, count: usize, types: &[ColumnType]| {
+                (path.to_string(), count, types.to_vec())
+            };
+            assert_eq!(
+                columns,
+                [
+                    column("http.request.method", 1, &[ColumnType::String]),
+                    column("http.status", 2, &[ColumnType::Number, ColumnType::String]),
+                    column("tags", 1, &[ColumnType::Array]),
+                    column("user", 1, &[ColumnType::Null]),
+                ]
+            );
+
+            block_on(add_facet_to_structured_logging_session(
+                session_id.clone(),
+                "http.request.method".to_string(),
+                "OR".to_string(),
+            ));
+            assert_eq!(totals(&session_id, "http.request.method"), [(json!("GET"), 1)]);
+
+            let entry = with_session(&session_id, |session| session.entries[0].clone());
+            assert_eq!(entry.get("tags.1").as_deref(), Some(&json!("b")));
+            assert_eq!(entry.get("/http/status").as_deref(), Some(&json!(200)));
+            assert_eq!(entry.get("http.request.missing"), None);
+        }
+
+        /// Imports a file into a new session, returning the session, the line
+        /// count after each batch and the bytes read from disk.
+        fn import(path: &Path) -> (String, Vec<u64>, u64) {
+            let session_id = block_on(start_structured_logging_session(Vec::new()));
+            let count = Arc::new(AtomicU64::new(0));
+            let reader = open_log_file(&path.to_string_lossy(), count.clone()).unwrap();
+            let mut batches = Vec::new();
+            let lines = import_lines(reader, &session_id, |lines| batches.push(lines)).unwrap();
+            assert_eq!(batches.last(), Some(&lines));
+            (session_id, batches, count.load(Ordering::Relaxed))
+        }
+
+        const IMPORTED: &str = "2024-01-02T03:04:05Z {\"msg\": \"json\"}\n\
+                                10:00:05 plain text without a timestamp\n\
+                                2024-01-02T03:04:06Z java.lang.Exception: boom\n\
+                                \tat com.example.Foo.bar(Foo.java:10)\n";
+
+        #[test]
+        fn import_plain_file() {
+            let path = std::env::temp_dir().join(format!("import_{}.log", Uuid::new_v4()));
+            fs::write(&path, IMPORTED).unwrap();
+            let (session_id, batches, bytes_read) = import(&path);
+            fs::remove_file(path).unwrap();
+
+            assert_eq!(batches, [4]);
+            assert_eq!(bytes_read, IMPORTED.len() as u64);
+            assert_eq!(
+                contents(&session_id),
+                [
+                    "{\"msg\": \"json\"}",
+                    "10:00:05 plain text without a timestamp",
+                    "java.lang.Exception: boom\n\tat com.example.Foo.bar(Foo.java:10)",
+                ]
+            );
+            let timestamps = with_session(&session_id, |session| {
+                session.entries.iter().map(|e| e.timestamp.clone()).collect::<Vec<String>>()
+            });
+            assert_eq!(timestamps, ["2024-01-02T03:04:05Z", "", "2024-01-02T03:04:06Z"]);
+        }
+
+        #[test]
+        fn import_gzip_file_in_batches() {
+            let lines = IMPORT_BATCH_LINES + 2;
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            for i in 0..lines {
+                writeln!(encoder, "2024-01-02T03:04:05Z {}", json!({"n": i})).unwrap();
+            }
+            let compressed = encoder.finish().unwrap();
+            let path = std::env::temp_dir().join(format!("import_{}.log.gz", Uuid::new_v4()));
+            fs::write(&path, &compressed).unwrap();
+            let (session_id, batches, bytes_read) = import(&path);
+            fs::remove_file(path).unwrap();
+
+            assert_eq!(batches, [IMPORT_BATCH_LINES as u64, lines as u64]);
+            assert_eq!(bytes_read, compressed.len() as u64);
+            let last = with_session(&session_id, |session| {
+                assert_eq!(session.entries.len(), lines);
+                session.entries.back().unwrap().data.clone()
+            });
+            assert_eq!(last, json!({"n": lines - 1}));
+        }
+
+        #[test]
+        fn export_csv() {
+            let session_id = timed_session(&[
+                json!({"msg": "plain", "n": 1}),
+                json!({"msg": "a, b", "n": 2}),
+                json!({"msg": "say \"hi\"", "n": 3}),
+                json!({"msg": "two\nlines"}),
+            ]);
+            let csv = export(&session_id, ExportFormat::Csv, Some(&["timestamp", "msg", "n"]));
+            assert_eq!(
+                csv,
+                "timestamp,msg,n\r\n\
+                 2024-01-02T03:04:00Z,plain,1\r\n\
+                 2024-01-02T03:04:01Z,\"a, b\",2\r\n\
+                 2024-01-02T03:04:02Z,\"say \"\"hi\"\"\",3\r\n\
+                 2024-01-02T03:04:03Z,\"two\nlines\",\r\n"
+            );
+
+            // Without columns, the timestamp, level and every discovered column
+            let csv = export(&session_id, ExportFormat::Csv, None);
+            let first_rows = "timestamp,level,msg,n\r\n2024-01-02T03:04:00Z,unknown,plain,1\r\n";
+            assert!(csv.starts_with(first_rows), "{}", csv);
+        }
+
+        #[test]
+        fn export_ndjson_and_text() {
+            let records = [json!({"msg": "first", "level": "warn"}), json!({"msg": "second"})];
+            let session_id = timed_session(&records);
+
+            let ndjson = export(&session_id, ExportFormat::Ndjson, None);
+            let lines = ndjson.lines().map(|line| serde_json::from_str(line).unwrap());
+            let lines = lines.collect::<Vec<Value>>();
+            assert_eq!(lines.len(), 2);
+            for (line, record) in lines.iter().zip(records.iter()) {
+                assert_eq!(line["data"], *record);
+                assert_eq!(line["content"], record.to_string());
+            }
+            assert_eq!([&lines[0]["level"], &lines[1]["level"]], ["warn", "unknown"]);
+            assert_eq!(lines[1]["timestamp"], "2024-01-02T03:04:01Z");
+
+            let text = export(&session_id, ExportFormat::Text, None);
+            let expected = format!(
+                "2024-01-02T03:04:00Z {}\n2024-01-02T03:04:01Z {}\n",
+                records[0], records[1]
+            );
+            assert_eq!(text, expected);
+        }
+
+        #[test]
+        fn aggregation() {
+            let session_id = timed_session(&[
+                json!({"status": 200, "ms": 10}),
+                json!({"status": 200, "ms": 30}),
+                json!({"status": 500, "ms": "100"}),
+                json!({"status": 200, "ms": 20}),
+                json!({"status": 404, "ms": "-"}),
+                json!({"status": 500, "ms": 300}),
+                json!({"ms": 5}),
+            ]);
+            let aggregate = |query: &str, group_by: Option<&str>| {
+                block_on(get_aggregation_for_structured_logging_session(
+                    session_id.clone(),
+                    query.to_string(),
+                    None,
+                    None,
+                    "ms".to_string(),
+                    group_by.map(str::to_string),
+                ))
+                .unwrap()
+            };
+
+            let aggregation = aggregate("", Some("status"));
+            assert_eq!(aggregation.skipped, 1);
+            let stats = aggregation.stats.unwrap();
+            assert_eq!((stats.count, stats.min, stats.max, stats.sum), (6, 5.0, 300.0, 465.0));
+            assert_eq!((stats.avg, stats.p50, stats.p95, stats.p99), (77.5, 20.0, 300.0, 300.0));
+            // Entries without the group field only count towards the totals
+            let groups = aggregation
+                .groups
+                .iter()
+                .map(|g| (g.value.as_str(), g.stats.count, g.stats.avg, g.stats.max))
+                .collect::<Vec<(&str, usize, f64, f64)>>();
+            assert_eq!(groups, [("200", 3, 20.0, 30.0), ("500", 2, 200.0, 300.0)]);
+
+            let aggregation = aggregate("status:500", None);
+            assert_eq!(aggregation.stats.unwrap().count, 2);
+            assert!(aggregation.groups.is_empty());
+            assert!(aggregate("status:302", None).stats.is_none());
+        }
+
+        #[test]
+        fn compare_sessions() {
+            let start = |rows: &[(&str, u16)]| {
+                let lines = rows
+                    .iter()
+                    .map(|(msg, status)| format!("- {}", json!({"msg": msg, "status": status})))
+                    .collect();
+                block_on(start_structured_logging_session(lines))
+            };
+            let base = start(&[
+                ("request served in 12 ms", 200),
+                ("request served in 15 ms", 200),
+                ("request served in 40 ms", 500),
+            ]);
+            let other = start(&[
+                ("request served in 9 ms", 500),
+                ("request served in 13 ms", 500),
+                ("connection to db lost", 500),
+            ]);
+            // Counted from the facet index in one session, by a scan in the other
+            block_on(add_facet_to_structured_logging_session(
+                base.clone(),
+                "status".to_string(),
+                "OR".to_string(),
+            ));
+
+            let comparison = block_on(compare_structured_logging_sessions(
+                base,
+                other,
+                Some(vec!["status".to_string()]),
+            ))
+            .unwrap();
+            let patterns = comparison.patterns;
+            assert_eq!(patterns.len(), 2);
+            assert_eq!(patterns[0].value, "connection to db lost");
+            assert_eq!((patterns[0].base_count, patterns[0].other_count), (0, 1));
+            assert_eq!(patterns[0].ratio, None);
+            assert_eq!((patterns[1].base_count, patterns[1].other_count), (3, 2));
+
+            let status = &comparison.facets[0].values;
+            assert_eq!(status[0].value, "200");
+            assert_eq!(status[0].ratio, None);
+            assert_eq!(status[1].value, "500");
+            assert_eq!(status[1].ratio, Some(3.0));
+        }
+
+        fn sorted(entries: &VecDeque<StructuredLogEntry>, column: &str, desc: bool) -> Vec<usize> {
+            let mut indices = (0..entries.len()).collect::<Vec<usize>>();
+            let sorting = [SortingState {
+                id: column.to_string(),
+                desc,
+            }];
+            apply_sorting(entries, &mut indices, &sorting);
+            indices
+        }
+
+        #[test]
+        fn sorting_mixed_types_and_missing_values() {
+            let entries = [
+                json!({"v": "b"}),
+                json!({"v": 2}),
+                json!({}),
+                json!({"v": 10}),
+                json!({"v": "a"}),
+                json!({"v": null}),
+                json!({"v": true}),
+                json!({"v": 2}),
+            ]
+            .into_iter()
+            .map(entry)
+            .collect::<VecDeque<StructuredLogEntry>>();
+
+            assert_eq!(sorted(&entries, "v", false), [6, 1, 7, 3, 4, 0, 2, 5]);
+            assert_eq!(sorted(&entries, "v", true), [0, 4, 3, 1, 7, 6, 2, 5]);
+        }
+
+        #[test]
+        fn sorting_by_timestamp_and_content() {
+            let entries = [
+                ("b", "2024-05-01T10:00:02Z"),
+                ("c", "2024-05-01T10:00:01Z"),
+                ("a", "not a time"),
+                ("d", "2024-05-01T10:00:01Z"),
+            ]
+            .into_iter()
+            .map(|(content, timestamp)| {
+                StructuredLogEntry::new(content.to_string(), timestamp.to_string(), json!({}))
+            })
+            .collect::<VecDeque<StructuredLogEntry>>();
+
+            assert_eq!(sorted(&entries, "timestamp", false), [1, 3, 0, 2]);
+            assert_eq!(sorted(&entries, "timestamp", true), [0, 1, 3, 2]);
+            assert_eq!(sorted(&entries, "content", false), [2, 0, 1, 3]);
+        }
+
+        #[test]
+        fn watch_fires_once_per_window() {
+            let mut watch = Watch::new(WatchRule {
+                name: "errors".to_string(),
+                query: "level:error".to_string(),
+                threshold: 2,
+                window_seconds: 60,
+            })
+            .unwrap();
+            let error = entry(json!({"level": "ERROR"}));
+            let info = entry(json!({"level": "INFO"}));
+            let now = Instant::now();
+
+            assert!(watch.check([&error, &info, &error].into_iter(), now).is_none());
+            let alert = watch.check([&error].into_iter(), now).unwrap();
+            assert_eq!(alert.count, 3);
+            assert!(watch.check([&error].into_iter(), now).is_none());
+        }
+    }
+}
+
+/// A small query language for filtering structured log entries.
+///
+/// Terms are combined with `AND` (or plain juxtaposition), `OR` and `NOT`/`-`,
+/// and may be grouped with parentheses. A term is either free text matched
+/// against the raw log line, or a field predicate evaluated against the parsed
+/// data, where nested fields are addressed with dotted paths:
+///
+/// - `level:error`, `msg:"connection refused"`: case-insensitive equality
+/// - `path:/health*`: wildcard match on the whole value
+/// - `path:/^\/api\/v[12]\//`: regular expression
+/// - `user:*`: field exists
+/// - `status>=500`, `latency<0.5`: numeric, or lexical for non-numbers
+/// - `status:[400 TO 499]`, `status:{400 TO 500}`: inclusive or exclusive range
+pub mod query {
+    use super::structured_logging::Level;
+    use regex::{Regex, RegexBuilder};
+    use serde_json::Value;
+    use std::borrow::Cow;
+    use std::fmt;
+
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct QueryError {
+        message: String,
+        position: usize,
+    }
+
+    impl fmt::Display for QueryError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} at position {}", self.message, self.position)
+        }
+    }
+
+    /// Anything a query can be evaluated against.
+    pub trait QueryTarget {
+        /// The raw text that free-text terms are matched against.
+        fn text(&self) -> &str;
+        /// Looks up a field by (dotted) path.
+        fn field(&self, path: &str) -> Option<Cow<'_, Value>>;
+    }
+
+    #[derive(Debug)]
+    pub enum Query {
+        And(Box<Query>, Box<Query>),
+        Or(Box<Query>, Box<Query>),
+        Not(Box<Query>),
+        Text(Matcher),
+        /// A field predicate. `name:value` words keep the whole word as a text
+        /// fallback for entries that don't have the field, so a search for
+        /// `https://host/x` or `a=b` still finds those strings in the text.
+        Field(String, Predicate, Option<Matcher>),
+    }
+
+    #[derive(Debug)]
+    pub enum Matcher {
+        /// Lowercased literal.
+        Literal(String),
+        Pattern(Regex),
+    }
+
+    #[derive(Debug)]
+    pub enum Predicate {
+        Exists,
+        Matches(Matcher),
+        Compare(Comparison, String),
+        Range {
+            low: String,
+            high: String,
+            inclusive: bool,
+        },
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    pub enum Comparison {
+        Lt,
+        Le,
+        Gt,
+        Ge,
+    }
+
+    /// Parses `input` into a query. Returns `Ok(None)` for a blank query, which
+    /// matches everything.
+    pub fn parse(input: &str) -> Result<Option<Query>, QueryError> {
+        let mut parser = Parser { input, pos: 0 };
+        parser.skip_whitespace();
+        if parser.at_end() {
+            return Ok(None);
+        }
+        let query = parser.parse_or()?;
+        parser.skip_whitespace();
+        if !parser.at_end() {
+            return Err(parser.error("unexpected ')'"));
+        }
+        Ok(Some(query))
+    }
+
+    struct Parser<'a> {
+        input: &'a str,
+        pos: usize,
+    }
+
+    impl<'a> Parser<'a> {
+        fn rest(&self) -> &'a str {
+            &self.input[self.pos..]
+        }
+
+        fn peek(&self) -> Option<char> {
+            self.rest().chars().next()
+        }
+
+        fn at_end(&self) -> bool {
+            self.pos >= self.input.len()
+        }
+
+        fn bump(&mut self) -> Option<char> {
+            let c = self.peek()?;
+            self.pos += c.len_utf8();
+            Some(c)
+        }
+
+        fn eat(&mut self, token: &str) -> bool {
+            if self.rest().starts_with(token) {
+                self.pos += token.len();
+                return true;
+            }
+            false
+        }
+
+        fn skip_whitespace(&mut self) {
+            while self.peek().is_some_and(char::is_whitespace) {
+                self.bump();
+            }
+        }
+
+        fn error(&self, message: &str) -> QueryError {
+            QueryError {
+                message: message.to_string(),
+                position: self.pos,
+            }
+        }
+
+        /// Consumes `keyword` if it appears as a whole word at the current position.
+        fn eat_keyword(&mut self, keyword: &str) -> bool {
+            let rest = self.rest();
+            if rest.starts_with(keyword)
+                && rest[keyword.len()..]
+                    .chars()
+                    .next()
+                    .is_none_or(|c| c.is_whitespace() || c == '(')
+            {
+                self.pos += keyword.len();
+                return true;
+            }
+            false
+        }
+
+        fn parse_or(&mut self) -> Result<Query, QueryError> {
+            let mut query = self.parse_and()?;
+            loop {
+                self.skip_whitespace();
+                if !self.eat_keyword("OR") {
+                    return Ok(query);
                 }
+                query = Query::Or(Box::new(query), Box::new(self.parse_and()?));
+            }
+        }
 
-                if !facet_matched_indices.is_empty() {
-                    facet_matches.push(HashMap::from([(
-                        facet.property.clone(),
-                        facet_matched_indices,
-                    )]));
+        fn parse_and(&mut self) -> Result<Query, QueryError> {
+            let mut query = self.parse_not()?;
+            loop {
+                self.skip_whitespace();
+                if self.at_end() || self.peek() == Some(')') {
+                    return Ok(query);
                 }
+                let start = self.pos;
+                if self.eat_keyword("OR") {
+                    self.pos = start;
+                    return Ok(query);
+                }
+                self.eat_keyword("AND");
+                query = Query::And(Box::new(query), Box::new(self.parse_not()?));
             }
+        }
 
-            let mut matched_indices = HashSet::new();
+        fn parse_not(&mut self) -> Result<Query, QueryError> {
+            self.skip_whitespace();
+            if self.eat_keyword("NOT") || self.eat("-") {
+                return Ok(Query::Not(Box::new(self.parse_not()?)));
+            }
+            self.parse_primary()
+        }
 
-            if !facet_matches.is_empty() {
-                // matched indices based on AND or OR
-                matched_indices = facet_matches[0]
-                    .iter()
-                    .map(|(_, indices)| indices.clone())
-                    .flatten()
-                    .collect();
-                for (property, indices) in facet_matches.iter().skip(1).flat_map(|m| m.iter()) {
-                    // get matchtype for property
-                    let facet = session
-                        .facets
-                        .iter()
-                        .find(|f| f.property == *property)
-                        .unwrap();
-                    match facet.match_type {
-                        MatchType::OR => {
-                            matched_indices = matched_indices.union(indices).cloned().collect();
-                        }
-                        MatchType::AND => {
-                            matched_indices =
-                                matched_indices.intersection(indices).cloned().collect();
-                        }
+        fn parse_primary(&mut self) -> Result<Query, QueryError> {
+            self.skip_whitespace();
+            match self.peek() {
+                None => Err(self.error("expected a search term")),
+                Some(')') => Err(self.error("unexpected ')'")),
+                Some('(') => {
+                    self.bump();
+                    let query = self.parse_or()?;
+                    self.skip_whitespace();
+                    if !self.eat(")") {
+                        return Err(self.error("expected ')'"));
                     }
+                    Ok(query)
                 }
+                Some('"') => Ok(Query::Text(Matcher::Literal(
+                    self.parse_phrase()?.to_lowercase(),
+                ))),
+                Some('/') if self.regex_ahead() => Ok(Query::Text(self.parse_regex()?)),
+                Some(_) => self.parse_term(),
             }
+        }
 
-            // Collect the filtered data based on matched indices if any filters are applied
-            if any_filtered {
-                // Apply search query if any and filter the matched indices
-                if !search_query.is_empty() {
-                    let search_query = search_query.to_lowercase();
-                    matched_indices = matched_indices
-                        .into_iter()
-                        .filter(|index| {
-                            let data = &session.entries[*index];
-
-                            if data.content.to_lowercase().contains(&search_query) {
-                                return true;
-                            }
+        fn parse_term(&mut self) -> Result<Query, QueryError> {
+            let start = self.pos;
+            let name = self.take_while(|c| !is_delimiter(c) && !":<>=".contains(c));
 
-                            return false;
-                        })
-                        .collect();
+            if !name.is_empty() {
+                let comparison = if self.eat(">=") {
+                    Some(Comparison::Ge)
+                } else if self.eat("<=") {
+                    Some(Comparison::Le)
+                } else if self.eat(">") {
+                    Some(Comparison::Gt)
+                } else if self.eat("<") {
+                    Some(Comparison::Lt)
+                } else {
+                    None
+                };
+                if let Some(comparison) = comparison.filter(|_| is_field_name(name)) {
+                    let value = self.parse_value()?;
+                    return Ok(Query::Field(
+                        name.to_string(),
+                        Predicate::Compare(comparison, value),
+                        None,
+                    ));
+                }
+                // A trailing colon (`Caused by:`) has nothing to match
+                if comparison.is_none()
+                    && is_field_name(name)
+                    && (self.eat(":") || self.eat("="))
+                    && self.peek().is_some_and(|c| !c.is_whitespace() && c != ')')
+                {
+                    let plain_word = self.peek() != Some('"') && !self.regex_ahead();
+                    let predicate = match self.parse_predicate()? {
+                        // `level` resolves to the canonical level name, so
+                        // accept the other spellings of it too
+                        Predicate::Matches(Matcher::Literal(literal)) if name == "level" => {
+                            let literal = Level::from_name(&literal)
+                                .and_then(|level| level.as_value().as_str())
+                                .map_or(literal, str::to_string);
+                            Predicate::Matches(Matcher::Literal(literal))
+                        }
+                        predicate => predicate,
+                    };
+                    let fallback = match predicate {
+                        Predicate::Matches(_) if plain_word => {
+                            Some(word_matcher(&self.input[start..self.pos], false))
+                        }
+                        _ => None,
+                    };
+                    return Ok(Query::Field(name.to_string(), predicate, fallback));
                 }
+            }
+
+            // Not a field predicate; treat the whole word as free text.
+            self.pos = start;
+            let word = self.take_while(|c| !is_delimiter(c));
+            Ok(Query::Text(word_matcher(word, false)))
+        }
 
-                for index in matched_indices.iter() {
-                    filtered_data.push(session.entries[*index].clone());
+        fn parse_predicate(&mut self) -> Result<Predicate, QueryError> {
+            match self.peek() {
+                Some('*') if self.rest()[1..].chars().next().is_none_or(is_delimiter) => {
+                    self.bump();
+                    Ok(Predicate::Exists)
                 }
-            } else {
-                // if any search query, apply query and then return data within offset and limit, otherwise return all data within offset and limit
-                if !search_query.is_empty() {
-                    let search_query = search_query.to_lowercase();
-                    for (_index, entry) in session.entries.iter().enumerate() {
-                        if entry.content.to_lowercase().contains(&search_query) {
-                            filtered_data.push(entry.clone());
-                            continue;
-                        }
+                Some(open @ ('[' | '{')) => {
+                    self.bump();
+                    self.skip_whitespace();
+                    let low = self.parse_value()?;
+                    self.skip_whitespace();
+                    if !self.eat_keyword("TO") {
+                        return Err(self.error("expected 'TO' in range"));
                     }
-                } else {
-                    for (_index, entry) in session.entries.iter().enumerate() {
-                        filtered_data.push(entry.clone());
+                    self.skip_whitespace();
+                    let high = self.take_while(|c| !c.is_whitespace() && c != ']' && c != '}');
+                    if high.is_empty() {
+                        return Err(self.error("expected upper bound of range"));
+                    }
+                    let high = high.to_string();
+                    self.skip_whitespace();
+                    let close = if open == '[' { "]" } else { "}" };
+                    if !self.eat(close) {
+                        return Err(self.error(&format!("expected '{}'", close)));
                     }
+                    Ok(Predicate::Range {
+                        low,
+                        high,
+                        inclusive: open == '[',
+                    })
+                }
+                Some('"') => Ok(Predicate::Matches(Matcher::Literal(
+                    self.parse_phrase()?.to_lowercase(),
+                ))),
+                Some('/') if self.regex_ahead() => Ok(Predicate::Matches(self.parse_regex()?)),
+                _ => {
+                    let word = self.take_while(|c| !is_delimiter(c));
+                    if word.is_empty() {
+                        return Err(self.error("expected a value"));
+                    }
+                    Ok(Predicate::Matches(word_matcher(word, true)))
                 }
             }
+        }
 
-            // Apply sorting, default to timestamp
-            if sorting.is_empty() {
-                sorting.push(SortingState {
-                    id: "timestamp".to_string(),
-                    desc: false,
-                });
+        fn parse_value(&mut self) -> Result<String, QueryError> {
+            if self.peek() == Some('"') {
+                return self.parse_phrase();
+            }
+            let word = self.take_while(|c| !is_delimiter(c));
+            if word.is_empty() {
+                return Err(self.error("expected a value"));
+            }
+            Ok(word.to_string())
+        }
+
+        fn parse_phrase(&mut self) -> Result<String, QueryError> {
+            let start = self.pos;
+            self.bump(); // opening quote
+            let mut phrase = String::new();
+            loop {
+                match self.bump() {
+                    Some('"') => return Ok(phrase),
+                    Some('\\') => match self.bump() {
+                        Some(c) => phrase.push(c),
+                        None => break,
+                    },
+                    Some(c) => phrase.push(c),
+                    None => break,
+                }
+            }
+            Err(QueryError {
+                message: "unterminated quoted phrase".to_string(),
+                position: start,
+            })
+        }
+
+        /// A `/` only starts a regex if a closing `/` is followed by the end of
+        /// the term; otherwise values such as `/health*` are plain words.
+        fn regex_ahead(&self) -> bool {
+            find_regex_end(self.rest()).is_some()
+        }
+
+        fn parse_regex(&mut self) -> Result<Matcher, QueryError> {
+            let start = self.pos;
+            let end = find_regex_end(self.rest()).unwrap();
+            let pattern = self.rest()[1..end].replace("\\/", "/");
+            self.pos += end + 1;
+            RegexBuilder::new(&pattern)
+                .case_insensitive(true)
+                .build()
+                .map(Matcher::Pattern)
+                .map_err(|e| QueryError {
+                    message: format!("invalid regular expression: {}", e),
+                    position: start,
+                })
+        }
+
+        fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+            let start = self.pos;
+            while self.peek().is_some_and(&predicate) {
+                self.bump();
             }
+            &self.input[start..self.pos]
+        }
+    }
 
-            let sorted_data = apply_sorting(filtered_data, &sorting);
+    fn is_delimiter(c: char) -> bool {
+        c.is_whitespace() || c == '(' || c == ')' || c == '"'
+    }
 
-            let total_count = session.entries.len() as u32;
+    /// Whether the left side of `:`, `=` or a comparison looks like a field
+    /// path rather than part of a time, URL or other text.
+    fn is_field_name(name: &str) -> bool {
+        name.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '@')
+            && name.chars().all(|c| c.is_alphanumeric() || "_.-@".contains(c))
+    }
 
-            return FilteredLogResult {
-                entries: sorted_data,
-                total: total_count,
-            };
+    /// Returns the byte index of the closing slash of a `/regex/` at the start
+    /// of `input`, if there is one that ends the term.
+    fn find_regex_end(input: &str) -> Option<usize> {
+        let mut escaped = false;
+        for (i, c) in input.char_indices().skip(1) {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '/' if !escaped => {
+                    return input[i + 1..]
+                        .chars()
+                        .next()
+                        .is_none_or(|c| c.is_whitespace() || c == ')')
+                        .then_some(i);
+                }
+                _ => escaped = false,
+            }
         }
+        None
+    }
 
-        return FilteredLogResult {
-            entries: Vec::new(),
-            total: 0,
+    /// Words containing `*` become wildcard patterns; anchored when matching a
+    /// whole field value, unanchored when searching free text.
+    fn word_matcher(word: &str, anchored: bool) -> Matcher {
+        if !word.contains('*') {
+            return Matcher::Literal(word.to_lowercase());
+        }
+        let pattern = word
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*");
+        let pattern = if anchored {
+            format!("^{}$", pattern)
+        } else {
+            pattern
         };
+        Matcher::Pattern(
+            RegexBuilder::new(&pattern)
+                .case_insensitive(true)
+                .build()
+                .unwrap(),
+        )
     }
 
-    fn apply_sorting(
-        mut data: Vec<StructuredLogEntry>,
-        sorting: &[SortingState],
-    ) -> Vec<StructuredLogEntry> {
-        data.sort_by(|a, b| {
-            for sort in sorting.iter() {
-                let key = &sort.id;
-                let desc = sort.desc;
-                let value_a = a.data.get(key);
-                let value_b = b.data.get(key);
-
-                if value_a.is_none() || value_b.is_none() {
-                    continue;
+    /// Where a query matched an entry, for highlighting.
+    #[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
+    pub struct Highlights {
+        /// Sorted, non-overlapping `[start, end)` byte ranges in the text that
+        /// free-text terms matched.
+        pub content: Vec<(usize, usize)>,
+        /// Paths of the fields whose terms matched, in query order.
+        pub fields: Vec<String>,
+    }
+
+    impl Query {
+        /// Collects what the terms that made `target` match matched. Negated
+        /// terms and the branches of an `OR` that didn't match contribute
+        /// nothing.
+        pub fn highlights(&self, target: &impl QueryTarget) -> Highlights {
+            let mut highlights = Highlights::default();
+            if self.matches(target) {
+                self.collect_highlights(target, &mut highlights);
+            }
+            highlights.content.sort_unstable();
+            highlights.content.dedup_by(|next, prev| {
+                if next.0 > prev.1 {
+                    return false;
                 }
+                prev.1 = prev.1.max(next.1);
+                true
+            });
+            highlights
+        }
 
-                let order = match (value_a, value_b) {
-                    (Some(va), Some(vb)) if va.is_string() && vb.is_string() => {
-                        let va = va.as_str().unwrap();
-                        let vb = vb.as_str().unwrap();
-                        if desc {
-                            vb.cmp(&va)
-                        } else {
-                            va.cmp(&vb)
+        /// Must only be called for queries that match `target`.
+        fn collect_highlights(&self, target: &impl QueryTarget, highlights: &mut Highlights) {
+            match self {
+                Query::And(a, b) => {
+                    a.collect_highlights(target, highlights);
+                    b.collect_highlights(target, highlights);
+                }
+                Query::Or(a, b) => {
+                    for q in [a, b] {
+                        if q.matches(target) {
+                            q.collect_highlights(target, highlights);
                         }
                     }
-                    (Some(va), Some(vb)) if va.is_number() && vb.is_number() => {
-                        let va = va.as_f64().unwrap();
-                        let vb = vb.as_f64().unwrap();
-                        if desc {
-                            vb.partial_cmp(&va).unwrap()
-                        } else {
-                            va.partial_cmp(&vb).unwrap()
-                        }
+                }
+                Query::Not(_) => {}
+                Query::Text(matcher) => {
+                    highlights.content.extend(matcher.text_spans(target.text()));
+                }
+                Query::Field(path, _, fallback) => {
+                    if let (None, Some(matcher)) = (target.field(path), fallback) {
+                        highlights.content.extend(matcher.text_spans(target.text()));
+                    } else if !highlights.fields.contains(path) {
+                        highlights.fields.push(path.clone());
                     }
-                    _ => continue,
-                };
-
-                if order != std::cmp::Ordering::Equal {
-                    return order;
                 }
             }
-            std::cmp::Ordering::Equal
-        });
-        data
+        }
+
+        pub fn matches(&self, target: &impl QueryTarget) -> bool {
+            match self {
+                Query::And(a, b) => a.matches(target) && b.matches(target),
+                Query::Or(a, b) => a.matches(target) || b.matches(target),
+                Query::Not(q) => !q.matches(target),
+                Query::Text(matcher) => matcher.matches_text(target.text()),
+                Query::Field(path, predicate, fallback) => match target.field(path) {
+                    Some(value) => predicate.matches(&value),
+                    None => fallback.as_ref().is_some_and(|m| m.matches_text(target.text())),
+                },
+            }
+        }
     }
 
-    fn update_unique_facet_values_for_logging_session(session_id: String) {
-        if let Some(session) = STRUCTURED_LOGGING_SESSIONS
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .get_mut(&session_id)
-        {
-            for facet in session.facets.iter_mut() {
-                let mut values: HashMap<String, u32> = HashMap::new();
-                for entry in session.entries.iter() {
-                    if let Some(value) = entry.data.get(&facet.property) {
-                        let key = serde_json::to_string(value).unwrap();
-                        *values.entry(key).or_insert(0) += 1;
+    impl Matcher {
+        fn matches_text(&self, text: &str) -> bool {
+            match self {
+                Matcher::Literal(literal) => text.to_lowercase().contains(literal.as_str()),
+                Matcher::Pattern(re) => re.is_match(text),
+            }
+        }
+
+        fn text_spans(&self, text: &str) -> Vec<(usize, usize)> {
+            match self {
+                Matcher::Literal(literal) => literal_spans(text, literal),
+                Matcher::Pattern(re) => re
+                    .find_iter(text)
+                    .filter(|m| !m.is_empty())
+                    .map(|m| (m.start(), m.end()))
+                    .collect(),
+            }
+        }
+    }
+
+    /// Byte ranges in `text` of the occurrences of the lowercased `literal`,
+    /// found the same way `Query::matches` does: in the lowercased text.
+    fn literal_spans(text: &str, literal: &str) -> Vec<(usize, usize)> {
+        if literal.is_empty() {
+            return Vec::new();
+        }
+        // Lowercasing can change byte lengths, so remember where each byte of
+        // the lowercased text came from
+        let mut lowered = String::with_capacity(text.len());
+        let mut origins = Vec::with_capacity(text.len() + 1);
+        for (i, c) in text.char_indices() {
+            let len = lowered.len();
+            lowered.extend(c.to_lowercase());
+            origins.resize(origins.len() + lowered.len() - len, i);
+        }
+        origins.push(text.len());
+
+        lowered
+            .match_indices(literal)
+            .map(|(start, m)| {
+                let last = origins[start + m.len() - 1];
+                let end = origins[start + m.len()..].iter().find(|o| **o > last).unwrap();
+                (origins[start], *end)
+            })
+            .collect()
+    }
+
+    impl Predicate {
+        fn matches(&self, value: &Value) -> bool {
+            if let Value::Array(values) = value {
+                return values.iter().any(|v| self.matches(v));
+            }
+            match self {
+                Predicate::Exists => !value.is_null(),
+                Predicate::Matches(Matcher::Literal(literal)) => {
+                    value_to_string(value).to_lowercase() == *literal
+                }
+                Predicate::Matches(Matcher::Pattern(re)) => re.is_match(&value_to_string(value)),
+                Predicate::Compare(comparison, operand) => {
+                    let ordering = compare(value, operand);
+                    match comparison {
+                        Comparison::Lt => ordering.is_lt(),
+                        Comparison::Le => ordering.is_le(),
+                        Comparison::Gt => ordering.is_gt(),
+                        Comparison::Ge => ordering.is_ge(),
                     }
                 }
-
-                for (value, total) in values.iter() {
-                    if let Some(facet_value) = facet.values.iter_mut().find(|v| v.value == *value) {
-                        facet_value.total = *total;
+                Predicate::Range {
+                    low,
+                    high,
+                    inclusive,
+                } => {
+                    let (low, high) = (compare(value, low), compare(value, high));
+                    if *inclusive {
+                        low.is_ge() && high.is_le()
                     } else {
-                        facet.values.push(FacetValue {
-                            value: value.to_string(),
-                            filtered: false,
-                            total: *total,
-                        });
+                        low.is_gt() && high.is_lt()
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn value_to_string(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    /// Compares numerically when both sides are numbers, by severity when both
+    /// are level names (so `level>=warn` works), lexically otherwise.
+    fn compare(value: &Value, operand: &str) -> std::cmp::Ordering {
+        let number = match value {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.parse::<f64>().ok(),
+            _ => None,
+        };
+        if let (Some(a), Ok(b)) = (number, operand.parse::<f64>()) {
+            return a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Less);
+        }
+        if let (Some(a), Some(b)) = (
+            value.as_str().and_then(Level::from_name),
+            Level::from_name(operand),
+        ) {
+            return a.cmp(&b);
+        }
+        if value.is_null() {
+            return std::cmp::Ordering::Less;
+        }
+        value_to_string(value).as_str().cmp(operand)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde_json::json;
+
+        struct Entry(String, Value);
+
+        impl QueryTarget for Entry {
+            fn text(&self) -> &str {
+                &self.0
+            }
+
+            fn field(&self, path: &str) -> Option<Cow<'_, Value>> {
+                path.split('.').try_fold(&self.1, |value, key| value.get(key)).map(Cow::Borrowed)
+            }
+        }
+
+        fn matches(query: &str, data: Value) -> bool {
+            let entry = Entry(data.to_string(), data);
+            parse(query).unwrap().unwrap().matches(&entry)
+        }
+
+        #[test]
+        fn blank_query() {
+            assert!(parse("  ").unwrap().is_none());
+        }
+
+        #[test]
+        fn boolean_operators() {
+            let query = "level:error AND status>=500 AND NOT path:/health*";
+            assert!(matches(query, json!({"level": "ERROR", "status": 503, "path": "/api"})));
+            assert!(!matches(query, json!({"level": "error", "status": 503, "path": "/healthz"})));
+            assert!(!matches(query, json!({"level": "error", "status": 404, "path": "/api"})));
+            let query = "level:warn OR (level:error -status:500)";
+            assert!(matches(query, json!({"level": "error", "status": 502})));
+            assert!(!matches(query, json!({"level": "error", "status": 500})));
+        }
+
+        #[test]
+        fn field_predicates() {
+            let data = json!({
+                "http": {"request": {"method": "GET"}},
+                "latency": "0.25",
+                "msg": "connection refused",
+            });
+            assert!(matches("http.request.method:get", data.clone()));
+            assert!(matches("http.request:*", data.clone()));
+            assert!(!matches("user:*", data.clone()));
+            assert!(matches("latency:[0.1 TO 0.25]", data.clone()));
+            assert!(!matches("latency:{0.1 TO 0.25}", data.clone()));
+            assert!(matches("msg:\"Connection refused\"", data.clone()));
+            assert!(matches("msg:/^conn.*used$/", data.clone()));
+            assert!(matches("\"connection refused\" refus*", data));
+        }
+
+        #[test]
+        fn words_that_are_not_field_predicates() {
+            let data = json!({"level": "info"});
+            let text = |text: &str| Entry(text.to_string(), data.clone());
+            let matches = |query: &str, text: &Entry| parse(query).unwrap().unwrap().matches(text);
+
+            let line = text("retrying at 10:00:05 via https://host/x?a=b");
+            for query in ["10:00", "10:00:05", "https://host/x", "a=b", "HTTPS://HOST/x?a=b"] {
+                assert!(matches(query, &line), "{}", query);
+            }
+            assert!(!matches("11:00", &line));
+            assert!(matches("Caused by:", &text("Caused by: java.io.IOException")));
+
+            // A field that exists is still matched as a field
+            assert!(matches("level=info", &text("no level here")));
+            assert!(!matches("level:debug", &text("level:debug")));
+            let h = parse("a=b").unwrap().unwrap().highlights(&line);
+            assert_eq!(h, Highlights { content: vec![(40, 43)], fields: Vec::new() });
+        }
+
+        #[test]
+        fn level_comparison() {
+            assert!(matches("level>=warn", json!({"level": "ERROR"})));
+            assert!(matches("level>=warn", json!({"level": "warning"})));
+            assert!(!matches("level>=warn", json!({"level": "info"})));
+            assert!(!matches("level>=warn", json!({"msg": "no level"})));
+        }
+
+        #[test]
+        fn level_spellings() {
+            for query in ["level:warn", "level:warning", "level=WRN", "level:\"W\""] {
+                assert!(matches(query, json!({"level": "warn"})), "{}", query);
+            }
+            assert!(matches("level:err OR level:fatal", json!({"level": "error"})));
+            assert!(!matches("level:err", json!({"level": "warn"})));
+            assert!(matches("level:w*", json!({"level": "warn"})));
+        }
+
+        #[test]
+        fn highlights() {
+            let highlight = |query: &str, text: &str, data: Value| {
+                parse(query).unwrap().unwrap().highlights(&Entry(text.to_string(), data))
+            };
+
+            let h = highlight("refused OR timeout", "Connection REFUSED, refused", json!({}));
+            assert_eq!(h.content, [(11, 18), (20, 27)]);
+
+            let h = highlight("/c[a-z]+n/ -refused", "Connection reset", json!({}));
+            assert_eq!(h.content, [(0, 10)]);
+
+            // "İ" grows by a byte when lowercased
+            let data = json!({"level": "error"});
+            let h = highlight("level:error straße", "İ STRASSE straße", data);
+            assert_eq!(h.content, [(11, 18)]);
+            assert_eq!(h.fields, ["level"]);
+
+            let h = highlight("level:info OR conn*", "Connection", json!({"level": "error"}));
+            assert_eq!(h, Highlights { content: vec![(0, 10)], fields: Vec::new() });
+        }
+
+        #[test]
+        fn syntax_errors() {
+            assert_eq!(parse("(level:error").unwrap_err().position, 12);
+            assert_eq!(parse("level:error)").unwrap_err().position, 11);
+            assert_eq!(parse("msg:\"open").unwrap_err().position, 4);
+            assert_eq!(parse("status:[1 TO").unwrap_err().position, 12);
+            assert!(parse("msg:/(/").unwrap_err().message.contains("regular expression"));
+        }
+    }
+}
+
+/// Drain-style log template mining: messages with the same number of tokens
+/// and the same leading tokens are grouped, and the tokens they differ in
+/// become wildcards.
+pub mod patterns {
+    use once_cell::sync::Lazy;
+    use regex::Regex;
+    use std::collections::HashMap;
+
+    const WILDCARD: &str = "<*>";
+    /// Number of leading tokens messages must share to be compared at all.
+    const DEPTH: usize = 2;
+    /// Minimum share of equal tokens for a message to join a template.
+    const SIMILARITY: f64 = 0.4;
+    /// Only the first tokens of long messages are mined.
+    const MAX_TOKENS: usize = 64;
+
+    static MASKS: Lazy<Vec<(Regex, &'static str)>> = Lazy::new(|| {
+        [
+            (r"[0-9a-fA-F]{8}-(?:[0-9a-fA-F]{4}-){3}[0-9a-fA-F]{12}", "<UUID>"),
+            (r"\b\d{1,3}(?:\.\d{1,3}){3}(?::\d+)?\b", "<IP>"),
+            (r"\b(?:0x[0-9a-fA-F]+|[0-9a-fA-F]{8,})\b", "<ID>"),
+            (r"[-+]?\b\d+(?:\.\d+)?\b", "<NUM>"),
+        ]
+        .into_iter()
+        .map(|(pattern, mask)| (Regex::new(pattern).unwrap(), mask))
+        .collect()
+    });
+
+    #[derive(Clone, Debug, serde::Serialize)]
+    pub struct Cluster {
+        pub id: usize,
+        pub template: String,
+        /// Number of retained messages matching the template.
+        pub count: usize,
+    }
+
+    #[derive(Debug, Default)]
+    pub struct TemplateMiner {
+        /// Token count and leading tokens -> ids of the templates in that group.
+        groups: HashMap<(usize, Vec<String>), Vec<usize>>,
+        /// Indexed by template id.
+        templates: Vec<Vec<String>>,
+        counts: Vec<usize>,
+    }
+
+    impl TemplateMiner {
+        /// Assigns `message` to a template, creating one if nothing is similar
+        /// enough, and returns the template id.
+        pub fn add(&mut self, message: &str) -> usize {
+            let masked = mask(message);
+            let tokens: Vec<String> =
+                masked.split_whitespace().take(MAX_TOKENS).map(str::to_string).collect();
+            let key = (
+                tokens.len(),
+                tokens
+                    .iter()
+                    .take(DEPTH)
+                    .map(|token| {
+                        if token.contains(|c: char| c.is_ascii_digit()) {
+                            WILDCARD.to_string()
+                        } else {
+                            token.clone()
+                        }
+                    })
+                    .collect(),
+            );
+            let group = self.groups.entry(key).or_default();
+
+            let best = group
+                .iter()
+                .map(|&id| (id, similarity(&self.templates[id], &tokens)))
+                .filter(|(_, score)| *score >= SIMILARITY)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((id, _)) = best {
+                for (template, token) in self.templates[id].iter_mut().zip(tokens.iter()) {
+                    if template != token {
+                        *template = WILDCARD.to_string();
                     }
                 }
+                self.counts[id] += 1;
+                return id;
+            }
+
+            let id = self.templates.len();
+            group.push(id);
+            self.templates.push(tokens);
+            self.counts.push(1);
+            id
+        }
+
+        /// Forgets one message of the given template, e.g. after eviction.
+        pub fn remove(&mut self, id: usize) {
+            if let Some(count) = self.counts.get_mut(id) {
+                *count = count.saturating_sub(1);
+            }
+        }
+
+        /// Forgets all messages but keeps the templates and their ids.
+        pub fn clear_counts(&mut self) {
+            self.counts.iter_mut().for_each(|count| *count = 0);
+        }
+
+        pub fn template(&self, id: usize) -> Option<String> {
+            self.templates.get(id).map(|tokens| tokens.join(" "))
+        }
+
+        /// Templates with at least one retained message, most frequent first.
+        pub fn clusters(&self) -> Vec<Cluster> {
+            let mut clusters: Vec<Cluster> = self
+                .counts
+                .iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .map(|(id, count)| Cluster {
+                    id,
+                    template: self.templates[id].join(" "),
+                    count: *count,
+                })
+                .collect();
+            clusters.sort_by(|a, b| b.count.cmp(&a.count).then(a.id.cmp(&b.id)));
+            clusters
+        }
+    }
+
+    /// Whether two templates, e.g. mined in different sessions, describe the
+    /// same messages: the same tokens except where either has a wildcard.
+    pub fn same_shape(a: &str, b: &str) -> bool {
+        let (a, b) = (a.split(' ').collect::<Vec<&str>>(), b.split(' ').collect::<Vec<&str>>());
+        a.len() == b.len()
+            && a.iter().zip(b.iter()).all(|(a, b)| a == b || *a == WILDCARD || *b == WILDCARD)
+    }
 
-                // Remove any facet values that are no longer present in the data
-                facet.values.retain(|v| values.contains_key(&v.value));
+    /// Replaces UUIDs, IP addresses, hex ids and numbers with placeholders.
+    pub fn mask(message: &str) -> String {
+        let mut masked = message.to_string();
+        for (re, placeholder) in MASKS.iter() {
+            if re.is_match(&masked) {
+                masked = re.replace_all(&masked, *placeholder).into_owned();
             }
         }
+        masked
+    }
+
+    fn similarity(template: &[String], tokens: &[String]) -> f64 {
+        if tokens.is_empty() {
+            return 1.0;
+        }
+        let equal = template
+            .iter()
+            .zip(tokens)
+            .filter(|(template, token)| template == token && template.as_str() != WILDCARD)
+            .count();
+        equal as f64 / tokens.len() as f64
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn masks_variable_parts() {
+            assert_eq!(
+                mask("user 42 from 10.0.0.1:8080 req 3f2a9c1e-0b4d-4c3e-9a7f-1b2c3d4e5f60"),
+                "user <NUM> from <IP> req <UUID>"
+            );
+            assert_eq!(mask("object 0x7ffde4a1 at deadbeef12"), "object <ID> at <ID>");
+            assert_eq!(mask("took 1.5 ms"), "took <NUM> ms");
+        }
+
+        #[test]
+        fn templates_of_the_same_shape() {
+            assert!(same_shape("Connection from <*> closed", "Connection from alice closed"));
+            assert!(!same_shape("Connection from <*> closed", "Connection from alice"));
+            assert!(!same_shape("Disk full on <*>", "Disk free on sda1"));
+        }
+
+        #[test]
+        fn groups_similar_messages() {
+            let mut miner = TemplateMiner::default();
+            let a = miner.add("Connection from alice closed");
+            let b = miner.add("Connection from bob closed");
+            let c = miner.add("Disk full on /dev/sda1");
+            assert_eq!(a, b);
+            assert_ne!(a, c);
+            assert_eq!(miner.template(a).unwrap(), "Connection from <*> closed");
+
+            miner.remove(c);
+            let clusters = miner.clusters();
+            assert_eq!(clusters.len(), 1);
+            assert_eq!(clusters[0].count, 2);
+        }
     }
 }
@@ -196,14 +196,40 @@ fn main() {
             logs::structured_logging::start_structured_logging_session,
             logs::structured_logging::repurpose_structured_logging_session,
             logs::structured_logging::end_structured_logging_session,
+            logs::structured_logging::save_structured_logging_session,
+            logs::structured_logging::list_saved_structured_logging_sessions,
+            logs::structured_logging::open_saved_structured_logging_session,
+            logs::structured_logging::rename_saved_structured_logging_session,
+            logs::structured_logging::delete_saved_structured_logging_session,
+            logs::structured_logging::list_log_views,
+            logs::structured_logging::save_log_view,
+            logs::structured_logging::delete_log_view,
+            logs::structured_logging::apply_log_view_to_structured_logging_session,
             logs::structured_logging::add_data_to_structured_logging_session,
+            logs::structured_logging::flush_structured_logging_session,
+            logs::structured_logging::import_file_to_structured_logging_session,
             logs::structured_logging::add_facet_to_structured_logging_session,
             logs::structured_logging::set_facet_match_type_for_structured_logging_session,
             logs::structured_logging::remove_facet_from_structured_logging_session,
             logs::structured_logging::get_facets_for_structured_logging_session,
             logs::structured_logging::get_columns_for_structured_logging_session,
+            logs::structured_logging::get_patterns_for_structured_logging_session,
+            logs::structured_logging::get_column_info_for_structured_logging_session,
             logs::structured_logging::set_filtered_for_facet_value,
+            logs::structured_logging::set_excluded_for_facet_value,
             logs::structured_logging::get_filtered_data_for_structured_logging_session,
+            logs::structured_logging::export_structured_logging_session,
+            logs::structured_logging::parse_log_line,
+            logs::structured_logging::set_retention_policy_for_structured_logging_session,
+            logs::structured_logging::get_eviction_stats_for_structured_logging_session,
+            logs::structured_logging::get_view_state_for_structured_logging_session,
+            logs::structured_logging::get_histogram_for_structured_logging_session,
+            logs::structured_logging::get_aggregation_for_structured_logging_session,
+            logs::structured_logging::compare_structured_logging_sessions,
+            logs::structured_logging::set_multiline_config_for_structured_logging_session,
+            logs::structured_logging::get_multiline_config_for_structured_logging_session,
+            logs::structured_logging::set_watch_rules_for_structured_logging_session,
+            logs::structured_logging::get_watch_rules_for_structured_logging_session,
         ])
         .setup(|_app| {
             #[cfg(target_os = "macos")]